@@ -2,19 +2,79 @@ use std::cmp::Ordering;
 use std::convert::TryFrom;
 use std::fmt;
 
-const DECIMALS: u32 = 6;
-const FACTOR: u64 = 10_u64.pow(DECIMALS);
+pub const DECIMALS: u32 = 6;
+pub const FACTOR: u64 = 10_u64.pow(DECIMALS);
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct FixedPointDecimal {
     value: u64,
 }
 
+/// Number of fixed-point multiplications and divisions performed, for comparing operation costs.
+#[cfg(feature = "instrument")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OpCost {
+    pub multiplications: u64,
+    pub divisions: u64,
+}
+
+#[cfg(feature = "instrument")]
+thread_local! {
+    static OP_COUNTS: std::cell::Cell<OpCost> = const {
+        std::cell::Cell::new(OpCost {
+            multiplications: 0,
+            divisions: 0,
+        })
+    };
+}
+
+/// Running totals of fixed-point operations on the current thread.
+#[cfg(feature = "instrument")]
+pub fn op_counts() -> OpCost {
+    OP_COUNTS.with(|counts| counts.get())
+}
+
+#[cfg(feature = "instrument")]
+fn record_op(multiplications: u64, divisions: u64) {
+    OP_COUNTS.with(|counts| {
+        let current = counts.get();
+        counts.set(OpCost {
+            multiplications: current.multiplications.wrapping_add(multiplications),
+            divisions: current.divisions.wrapping_add(divisions),
+        });
+    });
+}
+
+#[cfg(feature = "instrument")]
+impl OpCost {
+    pub fn since(self, earlier: OpCost) -> OpCost {
+        OpCost {
+            multiplications: self.multiplications.wrapping_sub(earlier.multiplications),
+            divisions: self.divisions.wrapping_sub(earlier.divisions),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    Floor,
+    Nearest,
+    Ceil,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum FixedPointError {
     Overflow,
     Underflow,
     DivisionByZero,
+    InvalidInput,
+    InvalidTicket,
+    NotWhole,
+    InsufficientRepayment,
+    NothingToUndo,
+    PrecisionLoss,
+    InvalidPercentage,
+    DepositTooSmall,
 }
 
 impl fmt::Display for FixedPointError {
@@ -23,6 +83,20 @@ impl fmt::Display for FixedPointError {
             FixedPointError::Overflow => write!(f, "Overflow occurred during operation!"),
             FixedPointError::Underflow => write!(f, "Underflow occurred during operation!"),
             FixedPointError::DivisionByZero => write!(f, "Division by zero!"),
+            FixedPointError::InvalidInput => write!(f, "Input is out of the valid domain!"),
+            FixedPointError::InvalidTicket => write!(f, "Unknown removal ticket!"),
+            FixedPointError::NotWhole => write!(f, "Value has a fractional part!"),
+            FixedPointError::InsufficientRepayment => {
+                write!(f, "Flash loan was not repaid in full!")
+            }
+            FixedPointError::NothingToUndo => write!(f, "No operation to undo!"),
+            FixedPointError::PrecisionLoss => {
+                write!(f, "Value has more fractional digits than supported!")
+            }
+            FixedPointError::InvalidPercentage => write!(f, "Invalid fee configuration!"),
+            FixedPointError::DepositTooSmall => {
+                write!(f, "First deposit is below the required minimum!")
+            }
         }
     }
 }
@@ -45,6 +119,10 @@ impl TryFrom<f64> for FixedPointDecimal {
     type Error = FixedPointError;
 
     fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if value.is_nan() || value.is_infinite() {
+            return Err(FixedPointError::InvalidInput);
+        }
+
         let scaled_value = value * FACTOR as f64;
         if scaled_value > u64::MAX as f64 || scaled_value < 0.0 {
             return Err(FixedPointError::Overflow);
@@ -57,6 +135,279 @@ impl TryFrom<f64> for FixedPointDecimal {
     }
 }
 
+impl FixedPointDecimal {
+    pub const ONE: FixedPointDecimal = FixedPointDecimal { value: FACTOR };
+    pub const MAX: FixedPointDecimal = FixedPointDecimal { value: u64::MAX };
+
+    /// Lossy counterpart to `TryFrom<f64>`: negatives and NaN become zero and values
+    /// beyond the representable range become `MAX`.
+    pub fn from_f64_saturating(value: f64) -> FixedPointDecimal {
+        if value.is_nan() {
+            return FixedPointDecimal::default();
+        }
+
+        FixedPointDecimal {
+            value: (value * FACTOR as f64).round() as u64,
+        }
+    }
+
+    pub fn from_parts(
+        integer: u64,
+        fractional_micros: u64,
+    ) -> Result<FixedPointDecimal, FixedPointError> {
+        if fractional_micros >= FACTOR {
+            return Err(FixedPointError::InvalidInput);
+        }
+
+        let value = integer
+            .checked_mul(FACTOR)
+            .and_then(|integer_value| integer_value.checked_add(fractional_micros))
+            .ok_or(FixedPointError::Overflow)?;
+        Ok(FixedPointDecimal { value })
+    }
+
+    pub const fn from_raw(value: u64) -> Self {
+        FixedPointDecimal { value }
+    }
+
+    pub const fn raw(self) -> u64 {
+        self.value
+    }
+
+    pub fn is_whole(self) -> bool {
+        self.value.is_multiple_of(FACTOR)
+    }
+
+    pub fn try_from_whole(value: FixedPointDecimal) -> Result<FixedPointDecimal, FixedPointError> {
+        if value.is_whole() {
+            Ok(value)
+        } else {
+            Err(FixedPointError::NotWhole)
+        }
+    }
+
+    pub fn mul_div(
+        self,
+        mul: FixedPointDecimal,
+        div: FixedPointDecimal,
+    ) -> Result<FixedPointDecimal, FixedPointError> {
+        self.mul_div_rounded(mul, div, RoundingMode::Floor)
+    }
+
+    pub fn mul_div_rounded(
+        self,
+        mul: FixedPointDecimal,
+        div: FixedPointDecimal,
+        rounding: RoundingMode,
+    ) -> Result<FixedPointDecimal, FixedPointError> {
+        #[cfg(feature = "instrument")]
+        record_op(1, 1);
+
+        if div.value == 0 {
+            return Err(FixedPointError::DivisionByZero);
+        }
+
+        let product = self.value as u128 * mul.value as u128;
+        let divisor = div.value as u128;
+        let result = match rounding {
+            RoundingMode::Floor => product / divisor,
+            RoundingMode::Nearest => (product + divisor / 2) / divisor,
+            RoundingMode::Ceil => product.div_ceil(divisor),
+        };
+        if result > u64::MAX as u128 {
+            return Err(FixedPointError::Overflow);
+        }
+
+        Ok(FixedPointDecimal {
+            value: result as u64,
+        })
+    }
+
+    pub fn ratio_to(
+        self,
+        reference: FixedPointDecimal,
+    ) -> Result<FixedPointDecimal, FixedPointError> {
+        self / reference
+    }
+
+    pub fn sub_or_zero(self, other: FixedPointDecimal) -> FixedPointDecimal {
+        FixedPointDecimal {
+            value: self.value.saturating_sub(other.value),
+        }
+    }
+
+    /// Like the checked power, but clamps to `MAX` when the result overflows.
+    pub fn saturating_pow(self, exp: u32) -> FixedPointDecimal {
+        self.checked_pow(exp).unwrap_or(FixedPointDecimal::MAX)
+    }
+
+    /// Square-and-multiply, so the cost grows with the bit length of `exp` rather than `exp`.
+    fn checked_pow(self, exp: u32) -> Result<FixedPointDecimal, FixedPointError> {
+        if exp == 0 || self == FixedPointDecimal::ONE {
+            return Ok(FixedPointDecimal::ONE);
+        }
+
+        let mut result = FixedPointDecimal::ONE;
+        let mut base = self;
+        let mut remaining_exp = exp;
+        loop {
+            if remaining_exp & 1 == 1 {
+                result = (result * base)?;
+            }
+            remaining_exp >>= 1;
+            if remaining_exp == 0 || result == FixedPointDecimal::default() {
+                return Ok(result);
+            }
+
+            base = (base * base)?;
+            if base == FixedPointDecimal::default() {
+                return Ok(base);
+            }
+        }
+    }
+
+    pub fn range_inclusive(
+        start: FixedPointDecimal,
+        end: FixedPointDecimal,
+        step_raw: u64,
+    ) -> impl Iterator<Item = FixedPointDecimal> {
+        std::iter::successors(Some(start), move |current| {
+            if step_raw == 0 {
+                return None;
+            }
+            current
+                .value
+                .checked_add(step_raw)
+                .map(|value| FixedPointDecimal { value })
+        })
+        .take_while(move |value| *value <= end)
+    }
+
+    pub fn dedup_sorted(values: &mut Vec<FixedPointDecimal>) {
+        values.sort();
+        values.dedup();
+    }
+
+    /// Parses a plain decimal string such as `"12.5"`. Any input with more than `DECIMALS`
+    /// fractional digits is rejected with `PrecisionLoss`, even if the extra digits are zeros.
+    pub fn from_str_exact(s: &str) -> Result<FixedPointDecimal, FixedPointError> {
+        let (integer_digits, fractional_digits) = s.split_once('.').unwrap_or((s, ""));
+        let is_digits = |digits: &str| digits.bytes().all(|byte| byte.is_ascii_digit());
+        if (integer_digits.is_empty() && fractional_digits.is_empty())
+            || !is_digits(integer_digits)
+            || !is_digits(fractional_digits)
+        {
+            return Err(FixedPointError::InvalidInput);
+        }
+        if fractional_digits.len() > DECIMALS as usize {
+            return Err(FixedPointError::PrecisionLoss);
+        }
+
+        let mut value: u64 = 0;
+        let padded_fraction = format!("{:0<width$}", fractional_digits, width = DECIMALS as usize);
+        for digit in integer_digits.bytes().chain(padded_fraction.bytes()) {
+            value = value
+                .checked_mul(10)
+                .and_then(|value| value.checked_add((digit - b'0') as u64))
+                .ok_or(FixedPointError::Overflow)?;
+        }
+
+        Ok(FixedPointDecimal { value })
+    }
+
+    pub fn to_trimmed_string(self) -> String {
+        let integer_part = self.value / FACTOR;
+        let fractional_part = self.value % FACTOR;
+        if fractional_part == 0 {
+            return format!("{}", integer_part);
+        }
+
+        let fractional_digits = format!("{:0width$}", fractional_part, width = DECIMALS as usize);
+        format!(
+            "{}.{}",
+            integer_part,
+            fractional_digits.trim_end_matches('0')
+        )
+    }
+
+    /// Canonical plain-decimal form for other decimal parsers, e.g. `"0.5"` or `"90"`.
+    pub fn to_decimal_string(self) -> String {
+        self.to_trimmed_string()
+    }
+
+    /// Formats with `sig` significant digits, rounding half up. No exponent notation is used.
+    /// Digits below the sixth decimal are not stored and are never shown, so any nonzero value
+    /// prints with at least one nonzero digit.
+    pub fn display_with_significant_digits(self, sig: usize) -> String {
+        if self.value == 0 {
+            return "0".to_string();
+        }
+
+        let sig = sig.max(1) as u32;
+        let digit_count = self.value.ilog10() + 1;
+        let dropped_digits = digit_count.saturating_sub(sig);
+        let divisor = 10_u128.pow(dropped_digits);
+        let rounded = (self.value as u128 + divisor / 2) / divisor * divisor;
+
+        let integer_part = rounded / FACTOR as u128;
+        let shown_decimals = DECIMALS.saturating_sub(dropped_digits);
+        if shown_decimals == 0 {
+            return format!("{}", integer_part);
+        }
+
+        let fractional_part = (rounded % FACTOR as u128) / 10_u128.pow(DECIMALS - shown_decimals);
+        format!(
+            "{}.{:0width$}",
+            integer_part,
+            fractional_part,
+            width = shown_decimals as usize
+        )
+    }
+
+    pub fn to_string_with_decimals(self, decimals: usize) -> String {
+        let shown_decimals = decimals.min(DECIMALS as usize);
+        let divisor = 10_u64.pow(DECIMALS - shown_decimals as u32);
+        let rounded = (self.value as u128 + divisor as u128 / 2) / divisor as u128;
+        let scale = 10_u128.pow(shown_decimals as u32);
+
+        let integer_part = rounded / scale;
+        if decimals == 0 {
+            return format!("{}", integer_part);
+        }
+
+        let fractional_part = rounded % scale;
+        format!(
+            "{}.{:0width$}{}",
+            integer_part,
+            fractional_part,
+            "0".repeat(decimals - shown_decimals),
+            width = shown_decimals
+        )
+    }
+}
+
+/// Builds a `FixedPointDecimal` from a literal, panicking if it is out of range.
+///
+/// ```
+/// use keep_liquidity::fixed_point_decimal::FixedPointDecimal;
+/// use keep_liquidity::fp;
+///
+/// assert_eq!(fp!(1.5), FixedPointDecimal::try_from(1.5).unwrap());
+/// assert_eq!(fp!(100), FixedPointDecimal::try_from(100u64).unwrap());
+/// ```
+#[macro_export]
+macro_rules! fp {
+    ($value:literal) => {
+        $crate::fixed_point_decimal::FixedPointDecimal::try_from($value).unwrap_or_else(|error| {
+            panic!(
+                "invalid fixed-point literal {}: {}",
+                stringify!($value),
+                error
+            )
+        })
+    };
+}
+
 impl std::ops::Add for FixedPointDecimal {
     type Output = Result<Self, FixedPointError>;
 
@@ -85,6 +436,9 @@ impl std::ops::Mul for FixedPointDecimal {
     type Output = Result<Self, FixedPointError>;
 
     fn mul(self, other: FixedPointDecimal) -> Self::Output {
+        #[cfg(feature = "instrument")]
+        record_op(1, 0);
+
         let result = (self.value as u128)
             .checked_mul(other.value as u128)
             .ok_or(FixedPointError::Overflow)?;
@@ -107,6 +461,9 @@ impl std::ops::Div for FixedPointDecimal {
     type Output = Result<Self, FixedPointError>;
 
     fn div(self, other: FixedPointDecimal) -> Self::Output {
+        #[cfg(feature = "instrument")]
+        record_op(0, 1);
+
         if other.value == 0 {
             return Err(FixedPointError::DivisionByZero);
         }
@@ -129,21 +486,57 @@ impl std::ops::Div for FixedPointDecimal {
     }
 }
 
+impl std::ops::Mul<u64> for FixedPointDecimal {
+    type Output = Result<Self, FixedPointError>;
+
+    fn mul(self, other: u64) -> Self::Output {
+        #[cfg(feature = "instrument")]
+        record_op(1, 0);
+
+        if let Some(result) = self.value.checked_mul(other) {
+            Ok(FixedPointDecimal { value: result })
+        } else {
+            Err(FixedPointError::Overflow)
+        }
+    }
+}
+
+impl std::ops::Div<u64> for FixedPointDecimal {
+    type Output = Result<Self, FixedPointError>;
+
+    fn div(self, other: u64) -> Self::Output {
+        #[cfg(feature = "instrument")]
+        record_op(0, 1);
+
+        if let Some(result) = self.value.checked_div(other) {
+            Ok(FixedPointDecimal { value: result })
+        } else {
+            Err(FixedPointError::DivisionByZero)
+        }
+    }
+}
+
 impl PartialEq<u64> for FixedPointDecimal {
     fn eq(&self, other: &u64) -> bool {
-        self.value == *other * FACTOR
+        self.value as u128 == *other as u128 * FACTOR as u128
     }
 }
 
 impl PartialEq<FixedPointDecimal> for u64 {
     fn eq(&self, other: &FixedPointDecimal) -> bool {
-        *self * FACTOR == other.value
+        *self as u128 * FACTOR as u128 == other.value as u128
+    }
+}
+
+impl Ord for FixedPointDecimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value.cmp(&other.value)
     }
 }
 
 impl PartialOrd for FixedPointDecimal {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.value.cmp(&other.value))
+        Some(self.cmp(other))
     }
 
     fn lt(&self, other: &Self) -> bool {
@@ -193,6 +586,72 @@ mod tests {
         assert_eq!(result.err().unwrap(), FixedPointError::Overflow);
     }
 
+    #[test]
+    fn test_try_from_f64_nan_and_infinity() {
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let result = FixedPointDecimal::try_from(value);
+            assert_eq!(result.err().unwrap(), FixedPointError::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_from_f64_saturating() {
+        assert_eq!(FixedPointDecimal::from_f64_saturating(1.5).value, 1_500_000);
+        assert_eq!(
+            FixedPointDecimal::from_f64_saturating(-1.5),
+            FixedPointDecimal::default()
+        );
+        assert_eq!(
+            FixedPointDecimal::from_f64_saturating(1e30),
+            FixedPointDecimal::MAX
+        );
+        assert_eq!(
+            FixedPointDecimal::from_f64_saturating(f64::INFINITY),
+            FixedPointDecimal::MAX
+        );
+        assert_eq!(
+            FixedPointDecimal::from_f64_saturating(f64::NAN),
+            FixedPointDecimal::default()
+        );
+    }
+
+    #[test]
+    fn test_fp_macro() {
+        assert_eq!(fp!(1.5), FixedPointDecimal::try_from(1.5).unwrap());
+        assert_eq!(fp!(100), FixedPointDecimal::try_from(100u64).unwrap());
+        assert_eq!(fp!(0.000001).value, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid fixed-point literal -1.5")]
+    fn test_fp_macro_panics_on_invalid_literal() {
+        fp!(-1.5);
+    }
+
+    #[test]
+    fn test_from_parts() {
+        let value = FixedPointDecimal::from_parts(1, 500_000).unwrap();
+        assert_eq!(value, FixedPointDecimal::try_from(1.5).unwrap());
+
+        let value = FixedPointDecimal::from_parts(0, 999_999).unwrap();
+        assert_eq!(value.value, 999_999);
+    }
+
+    #[test]
+    fn test_from_parts_overflow() {
+        let result = FixedPointDecimal::from_parts(u64::MAX / FACTOR + 1, 0);
+        assert_eq!(result.err().unwrap(), FixedPointError::Overflow);
+
+        let result = FixedPointDecimal::from_parts(u64::MAX / FACTOR, 999_999);
+        assert_eq!(result.err().unwrap(), FixedPointError::Overflow);
+    }
+
+    #[test]
+    fn test_from_parts_fraction_out_of_range() {
+        let result = FixedPointDecimal::from_parts(1, FACTOR);
+        assert_eq!(result.err().unwrap(), FixedPointError::InvalidInput);
+    }
+
     #[test]
     fn test_addition_success() {
         let num1 = FixedPointDecimal::try_from(12.345678).unwrap();
@@ -227,6 +686,14 @@ mod tests {
         assert_eq!(result.err().unwrap(), FixedPointError::Underflow);
     }
 
+    #[test]
+    fn test_sub_or_zero() {
+        let num1 = FixedPointDecimal::try_from(23.456789).unwrap();
+        let num2 = FixedPointDecimal::try_from(12.345678).unwrap();
+        assert_eq!(num1.sub_or_zero(num2).value, 11111111);
+        assert_eq!(num2.sub_or_zero(num1), FixedPointDecimal::default());
+    }
+
     #[test]
     fn test_multiplication_success() {
         let num1 = FixedPointDecimal::try_from(12.345678).unwrap();
@@ -263,6 +730,117 @@ mod tests {
         assert_eq!(result.err().unwrap(), FixedPointError::DivisionByZero);
     }
 
+    #[test]
+    fn test_multiplication_by_u64() {
+        let num = FixedPointDecimal::try_from(1.5).unwrap();
+        assert_eq!(
+            (num * 3).unwrap(),
+            FixedPointDecimal::try_from(4.5).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_multiplication_by_u64_overflow() {
+        let num = FixedPointDecimal {
+            value: u64::MAX / 2,
+        };
+        assert_eq!((num * 3).err().unwrap(), FixedPointError::Overflow);
+    }
+
+    #[test]
+    fn test_division_by_u64() {
+        let num = FixedPointDecimal::try_from(4.5).unwrap();
+        assert_eq!(
+            (num / 3).unwrap(),
+            FixedPointDecimal::try_from(1.5).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_division_by_u64_zero() {
+        let num = FixedPointDecimal::try_from(4.5).unwrap();
+        assert_eq!((num / 0).err().unwrap(), FixedPointError::DivisionByZero);
+    }
+
+    #[test]
+    fn test_mul_div_rounds_once() {
+        let num1 = FixedPointDecimal::try_from(10u64).unwrap();
+        let num2 = FixedPointDecimal::try_from(1u64).unwrap();
+        let num3 = FixedPointDecimal::try_from(3u64).unwrap();
+
+        assert_eq!(num1.mul_div(num2, num3).unwrap().value, 3333333);
+        assert_eq!(
+            (num1 * num2.mul_div(FixedPointDecimal::ONE, num3).unwrap())
+                .unwrap()
+                .value,
+            3333330
+        );
+    }
+
+    #[test]
+    fn test_mul_div_rounded_modes() {
+        let num1 = FixedPointDecimal::try_from(2u64).unwrap();
+        let num3 = FixedPointDecimal::try_from(3u64).unwrap();
+        let one = FixedPointDecimal::ONE;
+
+        assert_eq!(
+            num1.mul_div_rounded(one, num3, RoundingMode::Floor)
+                .unwrap()
+                .value,
+            666666
+        );
+        assert_eq!(
+            num1.mul_div_rounded(one, num3, RoundingMode::Nearest)
+                .unwrap()
+                .value,
+            666667
+        );
+        assert_eq!(
+            one.mul_div_rounded(one, num3, RoundingMode::Ceil)
+                .unwrap()
+                .value,
+            333334
+        );
+    }
+
+    #[test]
+    fn test_ratio_to() {
+        let target = FixedPointDecimal::try_from(90u64).unwrap();
+        let half = FixedPointDecimal::try_from(45u64).unwrap();
+
+        assert_eq!(target.ratio_to(target).unwrap(), FixedPointDecimal::ONE);
+        assert_eq!(
+            half.ratio_to(target).unwrap(),
+            FixedPointDecimal::try_from(0.5).unwrap()
+        );
+        assert_eq!(
+            target.ratio_to(FixedPointDecimal::default()).err(),
+            Some(FixedPointError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn test_mul_div_large_intermediate() {
+        let large = FixedPointDecimal { value: u64::MAX };
+        let two = FixedPointDecimal::try_from(2u64).unwrap();
+        assert_eq!(large.mul_div(two, two).unwrap(), large);
+        assert_eq!(
+            large.mul_div(two, FixedPointDecimal::ONE).err().unwrap(),
+            FixedPointError::Overflow
+        );
+    }
+
+    #[test]
+    fn test_mul_div_by_zero() {
+        let num = FixedPointDecimal::try_from(1u64).unwrap();
+        assert_eq!(
+            num.mul_div(num, FixedPointDecimal::default())
+                .err()
+                .unwrap(),
+            FixedPointError::DivisionByZero
+        );
+    }
+
     #[test]
     fn test_eq_with_u64() {
         let fixed_point = FixedPointDecimal::try_from(100u64).unwrap();
@@ -274,6 +852,19 @@ mod tests {
         assert_ne!(998u64, fixed_point);
     }
 
+    #[test]
+    fn test_eq_with_large_u64() {
+        let max = FixedPointDecimal { value: u64::MAX };
+        assert_ne!(max, u64::MAX);
+        assert_ne!(u64::MAX, max);
+
+        let largest_whole = u64::MAX / FACTOR;
+        let fixed_point = FixedPointDecimal::try_from(largest_whole).unwrap();
+        assert_eq!(fixed_point, largest_whole);
+        assert_ne!(fixed_point, largest_whole + 1);
+        assert_ne!(largest_whole + 1, fixed_point);
+    }
+
     #[test]
     fn test_eq_with_fixed_point() {
         let fixed_point_a = FixedPointDecimal::try_from(123u64).unwrap();
@@ -289,7 +880,7 @@ mod tests {
         let a = FixedPointDecimal::try_from(5.0).unwrap();
         let b = FixedPointDecimal::try_from(10.0).unwrap();
         assert!(a < b);
-        assert!(!(b < a));
+        assert!(b >= a);
     }
 
     #[test]
@@ -297,7 +888,7 @@ mod tests {
         let a = FixedPointDecimal::try_from(10.0).unwrap();
         let b = FixedPointDecimal::try_from(5.0).unwrap();
         assert!(a > b);
-        assert!(!(b > a));
+        assert!(b <= a);
     }
 
     #[test]
@@ -327,15 +918,254 @@ mod tests {
         assert!(a >= c);
     }
 
+    #[test]
+    fn test_range_inclusive() {
+        let start = FixedPointDecimal::try_from(1.0).unwrap();
+        let end = FixedPointDecimal::try_from(1.5).unwrap();
+        let values: Vec<_> = FixedPointDecimal::range_inclusive(start, end, 100_000).collect();
+
+        assert_eq!(values.len(), 6);
+        assert_eq!(values[0], start);
+        assert_eq!(values[5], end);
+    }
+
+    #[test]
+    fn test_range_inclusive_stops_at_max() {
+        let start = FixedPointDecimal {
+            value: u64::MAX - 2,
+        };
+        let values: Vec<_> =
+            FixedPointDecimal::range_inclusive(start, FixedPointDecimal::MAX, 2).collect();
+
+        assert_eq!(values, vec![start, FixedPointDecimal::MAX]);
+    }
+
+    #[test]
+    fn test_range_inclusive_zero_step_yields_start_once() {
+        let start = FixedPointDecimal::try_from(1.0).unwrap();
+        let end = FixedPointDecimal::try_from(2.0).unwrap();
+        assert_eq!(FixedPointDecimal::range_inclusive(start, end, 0).count(), 1);
+    }
+
+    #[test]
+    fn test_dedup_sorted() {
+        let mut values = vec![
+            FixedPointDecimal::try_from(1.5).unwrap(),
+            FixedPointDecimal::try_from(0.5).unwrap(),
+            FixedPointDecimal::try_from(1.5).unwrap(),
+            FixedPointDecimal::try_from(2.0).unwrap(),
+            FixedPointDecimal::try_from(0.5).unwrap(),
+        ];
+        FixedPointDecimal::dedup_sorted(&mut values);
+
+        assert_eq!(
+            values,
+            vec![
+                FixedPointDecimal::try_from(0.5).unwrap(),
+                FixedPointDecimal::try_from(1.5).unwrap(),
+                FixedPointDecimal::try_from(2.0).unwrap(),
+            ]
+        );
+    }
+
     #[test]
     fn test_display() {
         let value = FixedPointDecimal::try_from(123.456789).unwrap();
         assert_eq!(format!("{}", value), "123.456789");
     }
 
+    #[test]
+    fn test_to_string_with_decimals_rounds() {
+        let value = FixedPointDecimal::try_from(123.456789).unwrap();
+        assert_eq!(value.to_string_with_decimals(2), "123.46");
+        assert_eq!(value.to_string_with_decimals(0), "123");
+
+        let value = FixedPointDecimal::try_from(0.994).unwrap();
+        assert_eq!(value.to_string_with_decimals(2), "0.99");
+        assert_eq!(value.to_string_with_decimals(1), "1.0");
+    }
+
+    #[test]
+    fn test_to_string_with_decimals_pads() {
+        let value = FixedPointDecimal::try_from(123.456789).unwrap();
+        assert_eq!(value.to_string_with_decimals(6), "123.456789");
+        assert_eq!(value.to_string_with_decimals(8), "123.45678900");
+        assert_eq!(value.value, 123456789);
+    }
+
+    #[test]
+    fn test_to_trimmed_string() {
+        assert_eq!(
+            FixedPointDecimal::try_from(1.5)
+                .unwrap()
+                .to_trimmed_string(),
+            "1.5"
+        );
+        assert_eq!(
+            FixedPointDecimal::try_from(0.000001)
+                .unwrap()
+                .to_trimmed_string(),
+            "0.000001"
+        );
+        assert_eq!(
+            FixedPointDecimal::try_from(90u64)
+                .unwrap()
+                .to_trimmed_string(),
+            "90"
+        );
+    }
+
+    #[test]
+    fn test_to_decimal_string() {
+        assert_eq!(
+            FixedPointDecimal::try_from(0.5)
+                .unwrap()
+                .to_decimal_string(),
+            "0.5"
+        );
+        assert_eq!(
+            FixedPointDecimal::try_from(1_000_000u64)
+                .unwrap()
+                .to_decimal_string(),
+            "1000000"
+        );
+        assert_eq!(
+            FixedPointDecimal::try_from(12.345678)
+                .unwrap()
+                .to_decimal_string(),
+            "12.345678"
+        );
+        assert_eq!(FixedPointDecimal::default().to_decimal_string(), "0");
+    }
+
+    #[test]
+    fn test_display_with_significant_digits() {
+        let value = FixedPointDecimal::try_from(1234.567891).unwrap();
+        assert_eq!(value.display_with_significant_digits(3), "1230");
+        assert_eq!(value.display_with_significant_digits(6), "1234.57");
+
+        let value = FixedPointDecimal::try_from(0.000123).unwrap();
+        assert_eq!(value.display_with_significant_digits(2), "0.00012");
+    }
+
+    #[test]
+    fn test_display_with_significant_digits_smallest_value() {
+        let smallest = FixedPointDecimal::from_raw(1);
+        assert_eq!(smallest.display_with_significant_digits(3), "0.000001");
+        assert_eq!(
+            FixedPointDecimal::default().display_with_significant_digits(3),
+            "0"
+        );
+    }
+
+    #[test]
+    fn test_display_with_significant_digits_near_max() {
+        assert_eq!(
+            FixedPointDecimal::MAX.display_with_significant_digits(1),
+            "20000000000000"
+        );
+    }
+
+    #[test]
+    fn test_try_from_whole_accepts_whole_value() {
+        let value = FixedPointDecimal::try_from(42u64).unwrap();
+        assert!(value.is_whole());
+        assert_eq!(FixedPointDecimal::try_from_whole(value).unwrap(), value);
+    }
+
+    #[test]
+    fn test_try_from_whole_rejects_fractional_value() {
+        let value = FixedPointDecimal::try_from(42.000001).unwrap();
+        assert!(!value.is_whole());
+        assert_eq!(
+            FixedPointDecimal::try_from_whole(value).err().unwrap(),
+            FixedPointError::NotWhole
+        );
+    }
+
+    #[test]
+    fn test_raw_round_trip() {
+        let value = FixedPointDecimal::from_raw(1_500_000);
+        assert_eq!(value, FixedPointDecimal::try_from(1.5).unwrap());
+        assert_eq!(value.raw(), 1_500_000);
+    }
+
+    #[test]
+    fn test_one() {
+        assert_eq!(FixedPointDecimal::ONE, 1u64);
+    }
+
     #[test]
     fn test_default() {
         let default = FixedPointDecimal::default();
         assert_eq!(default.value, 0);
     }
+
+    #[test]
+    fn test_from_str_exact_accepts_six_fractional_digits() {
+        let value = FixedPointDecimal::from_str_exact("12.123456").unwrap();
+        assert_eq!(value.raw(), 12_123_456);
+    }
+
+    #[test]
+    fn test_from_str_exact_rejects_seven_fractional_digits() {
+        let result = FixedPointDecimal::from_str_exact("12.1234560");
+        assert_eq!(result, Err(FixedPointError::PrecisionLoss));
+    }
+
+    #[test]
+    fn test_from_str_exact_rejects_eight_fractional_digits() {
+        let result = FixedPointDecimal::from_str_exact("0.12345678");
+        assert_eq!(result, Err(FixedPointError::PrecisionLoss));
+    }
+
+    #[test]
+    fn test_from_str_exact_rejects_malformed_input() {
+        assert_eq!(
+            FixedPointDecimal::from_str_exact("1.2.3"),
+            Err(FixedPointError::InvalidInput)
+        );
+        assert_eq!(
+            FixedPointDecimal::from_str_exact("."),
+            Err(FixedPointError::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn test_saturating_pow_matches_checked_pow_in_range() {
+        let base = FixedPointDecimal::try_from(1.1).unwrap();
+
+        for exp in [0, 1, 2, 10] {
+            assert_eq!(base.saturating_pow(exp), base.checked_pow(exp).unwrap());
+        }
+        assert_eq!(base.saturating_pow(2).value, 1_210_000);
+    }
+
+    #[test]
+    fn test_saturating_pow_clamps_on_overflow() {
+        let base = FixedPointDecimal::try_from(1000).unwrap();
+
+        assert_eq!(base.checked_pow(5), Err(FixedPointError::Overflow));
+        assert_eq!(base.saturating_pow(5), FixedPointDecimal::MAX);
+    }
+
+    #[test]
+    fn test_saturating_pow_handles_huge_exponents() {
+        let slightly_above_one = FixedPointDecimal::from_raw(FACTOR + 1);
+        let half = FixedPointDecimal::try_from(0.5).unwrap();
+
+        assert_eq!(
+            FixedPointDecimal::ONE.saturating_pow(u32::MAX),
+            FixedPointDecimal::ONE
+        );
+        assert_eq!(
+            FixedPointDecimal::default().saturating_pow(u32::MAX),
+            FixedPointDecimal::default()
+        );
+        assert_eq!(
+            slightly_above_one.saturating_pow(u32::MAX),
+            FixedPointDecimal::MAX
+        );
+        assert_eq!(half.saturating_pow(u32::MAX), FixedPointDecimal::default());
+    }
 }
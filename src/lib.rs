@@ -0,0 +1,4 @@
+pub mod fixed_point_decimal;
+pub mod liquidity_pool;
+pub mod tokens;
+pub mod utils;
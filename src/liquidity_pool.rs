@@ -1,11 +1,20 @@
 use core::fmt;
+use std::collections::{HashMap, VecDeque};
 
-use crate::fixed_point_decimal::FixedPointError;
+#[cfg(feature = "instrument")]
+use crate::fixed_point_decimal::{op_counts, OpCost};
+use crate::fixed_point_decimal::{FixedPointDecimal, FixedPointError, RoundingMode, FACTOR};
 use crate::tokens::{LpTokenAmount, StakedTokenAmount, TokenAmount};
 use crate::utils::{Percentage, Price};
-use crate::FixedPointDecimal;
 
-#[derive(Debug)]
+const VOLUME_RETENTION_SECONDS: u64 = 24 * 60 * 60;
+const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+const MAX_UNDO_DEPTH: usize = 16;
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+const REBALANCE_TOLERANCE: FixedPointDecimal = FixedPointDecimal::from_raw(FACTOR / 10_000);
+
+#[derive(Debug, Clone)]
 pub struct LiquidityPool {
     price: Price,
     token_amount: TokenAmount,
@@ -14,6 +23,74 @@ pub struct LiquidityPool {
     liquidity_target: TokenAmount,
     min_fee: Percentage,
     max_fee: Percentage,
+    escrowed_lp_token_amount: LpTokenAmount,
+    pending_removals: HashMap<u64, LpTokenAmount>,
+    next_ticket_id: u64,
+    swap_volume: VecDeque<(u64, TokenAmount)>,
+    flash_fee: Percentage,
+    undo_stack: VecDeque<BalanceSnapshot>,
+    undo_suppression_depth: u32,
+    protocol_fee: Percentage,
+    protocol_treasury: TokenAmount,
+    rounding_drift: u128,
+    max_absolute_fee: Option<TokenAmount>,
+    minimum_first_deposit: TokenAmount,
+    net_principal: TokenAmount,
+    #[cfg(feature = "instrument")]
+    last_op_cost: OpCost,
+}
+
+#[derive(Debug, Clone)]
+struct BalanceSnapshot {
+    token_amount: TokenAmount,
+    staked_token_amount: StakedTokenAmount,
+    lp_token_amount: LpTokenAmount,
+    escrowed_lp_token_amount: LpTokenAmount,
+    pending_removals: HashMap<u64, LpTokenAmount>,
+    next_ticket_id: u64,
+    protocol_treasury: TokenAmount,
+    net_principal: TokenAmount,
+    swap_volume: VecDeque<(u64, TokenAmount)>,
+    rounding_drift: u128,
+}
+
+struct SwapQuote {
+    token_amount: TokenAmount,
+    fee: Percentage,
+    fee_amount: TokenAmount,
+    protocol_fee_amount: TokenAmount,
+}
+
+#[derive(Debug)]
+pub struct ImpactBreakdown {
+    pub fee_component: Percentage,
+    pub curve_component: Percentage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShockDirection {
+    Up,
+    Down,
+}
+
+#[derive(Debug)]
+pub struct PoolSnapshot {
+    pub tvl: TokenAmount,
+    pub token_share: Percentage,
+    pub staked_share: Percentage,
+    pub is_solvent: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SwapDirection {
+    StakedIn(StakedTokenAmount),
+    TokenIn(TokenAmount),
+    None,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RemovalTicket {
+    id: u64,
 }
 
 impl LiquidityPool {
@@ -22,8 +99,10 @@ impl LiquidityPool {
         liquidity_target: TokenAmount,
         min_fee: Percentage,
         max_fee: Percentage,
-    ) -> Self {
-        LiquidityPool {
+    ) -> Result<Self, FixedPointError> {
+        Self::validate_fee_config(min_fee.clone(), max_fee.clone())?;
+
+        Ok(LiquidityPool {
             price,
             token_amount: TokenAmount::default(),
             staked_token_amount: StakedTokenAmount::default(),
@@ -31,214 +110,4637 @@ impl LiquidityPool {
             liquidity_target,
             min_fee,
             max_fee,
+            escrowed_lp_token_amount: LpTokenAmount::default(),
+            pending_removals: HashMap::new(),
+            next_ticket_id: 0,
+            swap_volume: VecDeque::new(),
+            flash_fee: Percentage::default(),
+            undo_stack: VecDeque::new(),
+            undo_suppression_depth: 0,
+            protocol_fee: Percentage::default(),
+            protocol_treasury: TokenAmount::default(),
+            rounding_drift: 0,
+            max_absolute_fee: None,
+            minimum_first_deposit: TokenAmount::default(),
+            net_principal: TokenAmount::default(),
+            #[cfg(feature = "instrument")]
+            last_op_cost: OpCost::default(),
+        })
+    }
+
+    /// Fees must satisfy `min_fee <= max_fee <= 1`, so the fee never drops as liquidity drains.
+    pub fn validate_fee_config(
+        min_fee: Percentage,
+        max_fee: Percentage,
+    ) -> Result<(), FixedPointError> {
+        if min_fee.0 > max_fee.0 || max_fee.0 > FixedPointDecimal::ONE {
+            return Err(FixedPointError::InvalidPercentage);
+        }
+
+        Ok(())
+    }
+
+    /// Builds a pool that already holds balances, e.g. when migrating state. LP supply must be
+    /// zero exactly when both reserves are, and the pool must be solvent.
+    pub fn init_with_balances(
+        price: Price,
+        liquidity_target: TokenAmount,
+        min_fee: Percentage,
+        max_fee: Percentage,
+        token_amount: TokenAmount,
+        staked_token_amount: StakedTokenAmount,
+        lp_token_amount: LpTokenAmount,
+    ) -> Result<Self, FixedPointError> {
+        let has_reserves = token_amount.0 != FixedPointDecimal::default()
+            || staked_token_amount.0 != FixedPointDecimal::default();
+        let has_lp_supply = lp_token_amount.0 != FixedPointDecimal::default();
+        if has_reserves != has_lp_supply {
+            return Err(FixedPointError::InvalidInput);
         }
+
+        let mut pool = LiquidityPool {
+            token_amount,
+            staked_token_amount,
+            lp_token_amount,
+            ..Self::init(price, liquidity_target, min_fee, max_fee)?
+        };
+        if !pool.is_solvent()? {
+            return Err(FixedPointError::InvalidInput);
+        }
+        pool.net_principal = pool.tvl()?;
+
+        Ok(pool)
+    }
+
+    /// Stable, non-cryptographic FNV-1a hash over the raw balances and parameters.
+    pub fn state_fingerprint(&self) -> u64 {
+        let max_absolute_fee = self
+            .max_absolute_fee
+            .as_ref()
+            .map_or(u64::MAX, |max_absolute_fee| max_absolute_fee.0.raw());
+        [
+            self.price.0.raw(),
+            self.token_amount.0.raw(),
+            self.staked_token_amount.0.raw(),
+            self.lp_token_amount.0.raw(),
+            self.liquidity_target.0.raw(),
+            self.min_fee.0.raw(),
+            self.max_fee.0.raw(),
+            self.escrowed_lp_token_amount.0.raw(),
+            self.next_ticket_id,
+            self.flash_fee.0.raw(),
+            self.protocol_fee.0.raw(),
+            self.protocol_treasury.0.raw(),
+            max_absolute_fee,
+            self.minimum_first_deposit.0.raw(),
+            self.net_principal.0.raw(),
+        ]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+        })
+    }
+
+    pub fn balances(&self) -> (TokenAmount, StakedTokenAmount, LpTokenAmount) {
+        (
+            self.token_amount.clone(),
+            self.staked_token_amount.clone(),
+            self.lp_token_amount.clone(),
+        )
+    }
+
+    /// A pool is empty when it has no LP supply, whatever dust its reserves may hold.
+    pub fn is_empty(&self) -> bool {
+        self.lp_token_amount.0 == FixedPointDecimal::default()
     }
 
     pub fn add_liquidity(
         &mut self,
         amount_of_new_tokens: TokenAmount,
     ) -> Result<LpTokenAmount, FixedPointError> {
-        let current_pool_value = self.current_pool_value()?;
-        let minted_token_amount =
-            if current_pool_value.0 == FixedPointDecimal::try_from(0u64).unwrap() {
-                amount_of_new_tokens.0
-            } else {
-                let ownership_ratio = (self.lp_token_amount.0 / current_pool_value.0)?;
-                (amount_of_new_tokens.0 * ownership_ratio)?
-            };
+        #[cfg(feature = "instrument")]
+        let counts_before = op_counts();
+
+        let minted_token_amount = if self.is_empty() {
+            if amount_of_new_tokens.0 < self.minimum_first_deposit.0 {
+                return Err(FixedPointError::DepositTooSmall);
+            }
+            amount_of_new_tokens.0
+        } else {
+            amount_of_new_tokens
+                .0
+                .mul_div(self.lp_token_amount.0, self.current_pool_value()?.0)?
+        };
 
-        self.token_amount.0 = (self.token_amount.0 + amount_of_new_tokens.0)?;
-        self.lp_token_amount.0 = (self.lp_token_amount.0 + minted_token_amount)?;
+        let token_amount = (self.token_amount.0 + amount_of_new_tokens.0)?;
+        let lp_token_amount = (self.lp_token_amount.0 + minted_token_amount)?;
+        let net_principal = (self.net_principal.0 + amount_of_new_tokens.0)?;
+
+        self.push_undo_snapshot();
+        self.token_amount.0 = token_amount;
+        self.lp_token_amount.0 = lp_token_amount;
+        self.net_principal.0 = net_principal;
+        #[cfg(feature = "instrument")]
+        self.record_op_cost(counts_before);
 
         Ok(LpTokenAmount(minted_token_amount))
     }
 
+    /// Largest deposit for which neither the new reserve, the new LP supply nor the tracked net
+    /// principal in `add_liquidity` overflows.
+    pub fn max_safe_deposit(&self) -> Result<TokenAmount, FixedPointError> {
+        let reserve_limit = (u64::MAX - self.token_amount.0.raw()) as u128;
+        let principal_limit = (u64::MAX - self.net_principal.0.raw()) as u128;
+        let lp_headroom = (u64::MAX - self.lp_token_amount.0.raw()) as u128;
+        let lp_limit = if self.is_empty() {
+            lp_headroom
+        } else {
+            // Largest deposit with deposit * lp_supply / pool_value <= lp_headroom
+            let pool_value = self.current_pool_value()?.0.raw() as u128;
+            (((lp_headroom + 1) * pool_value).saturating_sub(1))
+                / self.lp_token_amount.0.raw() as u128
+        };
+
+        Ok(TokenAmount(FixedPointDecimal::from_raw(
+            reserve_limit.min(principal_limit).min(lp_limit) as u64,
+        )))
+    }
+
+    /// Credits base tokens to the reserve without minting LP tokens, raising the LP token price.
+    pub fn donate(&mut self, token_amount: TokenAmount) -> Result<(), FixedPointError> {
+        let new_token_amount = (self.token_amount.0 + token_amount.0)?;
+
+        self.push_undo_snapshot();
+        self.token_amount.0 = new_token_amount;
+
+        Ok(())
+    }
+
+    /// Deposits `token_amount` plus the staked tokens needed to keep the composition unchanged.
+    /// The staked side is rounded up and the minted LP down, both in the pool's favour.
+    pub fn add_balanced_liquidity(
+        &mut self,
+        token_amount: TokenAmount,
+    ) -> Result<(StakedTokenAmount, LpTokenAmount), FixedPointError> {
+        if self.is_empty() {
+            let minted_token_amount = self.add_liquidity(token_amount)?;
+            return Ok((StakedTokenAmount::default(), minted_token_amount));
+        }
+
+        let required_staked_token_amount = self.staked_token_amount.0.mul_div_rounded(
+            token_amount.0,
+            self.token_amount.0,
+            RoundingMode::Ceil,
+        )?;
+        let minted_token_amount = self
+            .lp_token_amount
+            .0
+            .mul_div(token_amount.0, self.token_amount.0)?;
+
+        let new_token_amount = (self.token_amount.0 + token_amount.0)?;
+        let new_staked_token_amount = (self.staked_token_amount.0 + required_staked_token_amount)?;
+        let new_lp_token_amount = (self.lp_token_amount.0 + minted_token_amount)?;
+        let required_staked_token_value = self
+            .price
+            .value_of(&StakedTokenAmount(required_staked_token_amount))?;
+        let new_net_principal =
+            ((self.net_principal.0 + token_amount.0)? + required_staked_token_value.0)?;
+
+        self.push_undo_snapshot();
+        self.token_amount.0 = new_token_amount;
+        self.staked_token_amount.0 = new_staked_token_amount;
+        self.lp_token_amount.0 = new_lp_token_amount;
+        self.net_principal.0 = new_net_principal;
+
+        Ok((
+            StakedTokenAmount(required_staked_token_amount),
+            LpTokenAmount(minted_token_amount),
+        ))
+    }
+
     pub fn remove_liquidity(
         &mut self,
         lp_token_amount: LpTokenAmount,
     ) -> Result<(TokenAmount, StakedTokenAmount), FixedPointError> {
+        #[cfg(feature = "instrument")]
+        let counts_before = op_counts();
+
+        let (token_amount_to_return, staked_token_to_return, _) =
+            self.quote_remove_liquidity(LpTokenAmount(lp_token_amount.0))?;
+
+        let removed = self.apply_removal(
+            lp_token_amount,
+            token_amount_to_return,
+            staked_token_to_return,
+        )?;
+        #[cfg(feature = "instrument")]
+        self.record_op_cost(counts_before);
+
+        Ok(removed)
+    }
+
+    /// Like `remove_liquidity`, but rounds the proportional share with `rounding`. Outputs are
+    /// capped at the pool's balances, so `Ceil` never pays out more than is available.
+    pub fn remove_liquidity_rounded(
+        &mut self,
+        lp_token_amount: LpTokenAmount,
+        rounding: RoundingMode,
+    ) -> Result<(TokenAmount, StakedTokenAmount), FixedPointError> {
+        self.ensure_lp_available(&lp_token_amount)?;
+
+        let base_token_amount_to_return = self
+            .token_amount
+            .0
+            .mul_div_rounded(lp_token_amount.0, self.lp_token_amount.0, rounding)?
+            .min(self.token_amount.0);
+        let base_staked_token_amount_to_return = self
+            .staked_token_amount
+            .0
+            .mul_div_rounded(lp_token_amount.0, self.lp_token_amount.0, rounding)?
+            .min(self.staked_token_amount.0);
+
+        let (token_amount_to_return, staked_token_to_return, _) = self.apply_removal_fee(
+            base_token_amount_to_return,
+            base_staked_token_amount_to_return,
+        )?;
+
+        self.apply_removal(
+            lp_token_amount,
+            token_amount_to_return,
+            staked_token_to_return,
+        )
+    }
+
+    fn apply_removal(
+        &mut self,
+        lp_token_amount: LpTokenAmount,
+        token_amount_to_return: TokenAmount,
+        staked_token_to_return: StakedTokenAmount,
+    ) -> Result<(TokenAmount, StakedTokenAmount), FixedPointError> {
+        let remaining_lp_token_amount = (self.lp_token_amount.0 - lp_token_amount.0)?;
+        let remaining_token_amount = (self.token_amount.0 - token_amount_to_return.0)?;
+        let remaining_staked_token_amount =
+            (self.staked_token_amount.0 - staked_token_to_return.0)?;
+        // Fees earned by the withdrawn share leave with it, so only its principal is removed
+        let remaining_net_principal = self
+            .net_principal
+            .0
+            .mul_div(remaining_lp_token_amount, self.lp_token_amount.0)?;
+
+        self.push_undo_snapshot();
+        self.lp_token_amount.0 = remaining_lp_token_amount;
+        self.token_amount.0 = remaining_token_amount;
+        self.staked_token_amount.0 = remaining_staked_token_amount;
+        self.net_principal.0 = remaining_net_principal;
+
+        Ok((token_amount_to_return, staked_token_to_return))
+    }
+
+    /// Removes liquidity and swaps the staked share back into the pool, paying the swap fee.
+    /// The pool is left untouched if the remaining reserve cannot cover that swap.
+    pub fn remove_liquidity_single_sided(
+        &mut self,
+        lp_token_amount: LpTokenAmount,
+    ) -> Result<TokenAmount, FixedPointError> {
+        let snapshot = self.clone();
+        let result = self
+            .with_single_undo_snapshot(|pool| pool.execute_single_sided_removal(lp_token_amount));
+        if result.is_err() {
+            *self = snapshot;
+        }
+        result
+    }
+
+    pub fn quote_remove_liquidity(
+        &self,
+        lp_token_amount: LpTokenAmount,
+    ) -> Result<(TokenAmount, StakedTokenAmount, Percentage), FixedPointError> {
+        self.ensure_lp_available(&lp_token_amount)?;
+
         let proportional_share = (lp_token_amount.0 / self.lp_token_amount.0)?;
         let base_token_amount_to_return = (proportional_share * self.token_amount.0)?;
         let base_staked_token_amount_to_return = (proportional_share * self.staked_token_amount.0)?;
 
+        self.apply_removal_fee(
+            base_token_amount_to_return,
+            base_staked_token_amount_to_return,
+        )
+    }
+
+    fn ensure_lp_available(&self, lp_token_amount: &LpTokenAmount) -> Result<(), FixedPointError> {
+        let available_lp_token_amount = (self.lp_token_amount.0 - self.escrowed_lp_token_amount.0)?;
+        if lp_token_amount.0 > available_lp_token_amount {
+            return Err(FixedPointError::Underflow);
+        }
+
+        Ok(())
+    }
+
+    fn apply_removal_fee(
+        &self,
+        base_token_amount_to_return: FixedPointDecimal,
+        base_staked_token_amount_to_return: FixedPointDecimal,
+    ) -> Result<(TokenAmount, StakedTokenAmount, Percentage), FixedPointError> {
         let final_liquidity = (self.token_amount.0 - base_token_amount_to_return)?;
         let fee = self.calculate_fee(TokenAmount(final_liquidity))?;
 
         let token_amount_to_return = self.apply_fee(base_token_amount_to_return, &fee)?;
         let staked_token_to_return = self.apply_fee(base_staked_token_amount_to_return, &fee)?;
 
-        self.lp_token_amount.0 = (self.lp_token_amount.0 - lp_token_amount.0)?;
-        self.token_amount.0 = (self.token_amount.0 - token_amount_to_return)?;
-        self.staked_token_amount.0 = (self.staked_token_amount.0 - staked_token_to_return)?;
-
         Ok((
             TokenAmount(token_amount_to_return),
             StakedTokenAmount(staked_token_to_return),
+            fee,
         ))
     }
 
+    pub fn request_removal(
+        &mut self,
+        lp_token_amount: LpTokenAmount,
+    ) -> Result<RemovalTicket, FixedPointError> {
+        let escrowed_lp_token_amount = (self.escrowed_lp_token_amount.0 + lp_token_amount.0)?;
+        if escrowed_lp_token_amount > self.lp_token_amount.0 {
+            return Err(FixedPointError::Underflow);
+        }
+
+        let id = self.next_ticket_id;
+        let next_ticket_id = id.checked_add(1).ok_or(FixedPointError::Overflow)?;
+
+        self.push_undo_snapshot();
+        self.next_ticket_id = next_ticket_id;
+        self.escrowed_lp_token_amount.0 = escrowed_lp_token_amount;
+        self.pending_removals.insert(id, lp_token_amount);
+
+        Ok(RemovalTicket { id })
+    }
+
+    /// Finalizes a queued removal. The ticket stays pending if the removal fails.
+    pub fn claim_removal(
+        &mut self,
+        ticket: RemovalTicket,
+    ) -> Result<(TokenAmount, StakedTokenAmount), FixedPointError> {
+        let snapshot = self.clone();
+        let result = self.with_single_undo_snapshot(|pool| pool.execute_claim_removal(ticket));
+        if result.is_err() {
+            *self = snapshot;
+        }
+        result
+    }
+
     pub fn swap(
         &mut self,
         staked_token_amount: StakedTokenAmount,
     ) -> Result<TokenAmount, FixedPointError> {
-        let base_staked_token_value = self.calculate_staked_token_value(&staked_token_amount)?;
-        let final_token_amount = (self.token_amount.0 - base_staked_token_value.0)?;
+        #[cfg(feature = "instrument")]
+        let counts_before = op_counts();
 
-        let fee = self.calculate_fee(TokenAmount(final_token_amount))?;
-        let staked_token_value = self.apply_fee(base_staked_token_value.0, &fee)?;
+        let quote = self.quote_swap_with_fee(&staked_token_amount)?;
+        let rounding_error = self.swap_rounding_error(&staked_token_amount, &quote);
 
-        self.staked_token_amount.0 = (self.staked_token_amount.0 + staked_token_amount.0)?;
-        self.token_amount.0 = (self.token_amount.0 - staked_token_value)?;
+        let staked_token_amount = (self.staked_token_amount.0 + staked_token_amount.0)?;
+        let token_amount =
+            ((self.token_amount.0 - quote.token_amount.0)? - quote.protocol_fee_amount.0)?;
+        let protocol_treasury = (self.protocol_treasury.0 + quote.protocol_fee_amount.0)?;
 
-        Ok(TokenAmount(staked_token_value))
+        self.push_undo_snapshot();
+        self.staked_token_amount.0 = staked_token_amount;
+        self.token_amount.0 = token_amount;
+        self.protocol_treasury.0 = protocol_treasury;
+        self.rounding_drift = self.rounding_drift.saturating_add(rounding_error);
+        #[cfg(feature = "instrument")]
+        self.record_op_cost(counts_before);
+
+        Ok(quote.token_amount)
     }
 
-    fn calculate_fee(&self, final_liquidity: TokenAmount) -> Result<Percentage, FixedPointError> {
-        if final_liquidity.0 >= self.liquidity_target.0 {
-            Ok(Percentage(self.min_fee.0))
-        } else {
-            let max_min_fee_difference = (self.max_fee.0 - self.min_fee.0)?;
-            let liquidity_to_target_ratio = (final_liquidity.0 / self.liquidity_target.0)?;
-            let fee = (self.max_fee.0 - (max_min_fee_difference * liquidity_to_target_ratio)?)?;
+    /// Swaps as much of `staked_token_amount` as the base reserve can cover and returns the
+    /// filled input with its output. The unfilled remainder is left with the caller.
+    pub fn swap_partial(
+        &mut self,
+        staked_token_amount: StakedTokenAmount,
+    ) -> Result<(StakedTokenAmount, TokenAmount), FixedPointError> {
+        let filled_staked_token_amount =
+            if self.calculate_staked_token_value(&staked_token_amount)?.0 <= self.token_amount.0 {
+                staked_token_amount
+            } else {
+                // Largest raw input whose truncated value still fits in the reserve
+                let max_staked_raw = ((self.token_amount.0.raw() as u128 + 1) * FACTOR as u128 - 1)
+                    / self.price.0.raw() as u128;
+                StakedTokenAmount(FixedPointDecimal::from_raw(
+                    max_staked_raw.min(staked_token_amount.0.raw() as u128) as u64,
+                ))
+            };
 
-            Ok(Percentage(fee))
+        let token_amount = self.swap(filled_staked_token_amount.clone())?;
+        Ok((filled_staked_token_amount, token_amount))
+    }
+
+    pub fn quote_add_then_swap(
+        &self,
+        deposit: TokenAmount,
+        swap_in: StakedTokenAmount,
+    ) -> Result<(LpTokenAmount, TokenAmount), FixedPointError> {
+        let mut simulated_pool = self.clone();
+        let minted_lp_token_amount = simulated_pool.add_liquidity(deposit)?;
+        let token_amount = simulated_pool.swap(swap_in)?;
+
+        Ok((minted_lp_token_amount, token_amount))
+    }
+
+    pub fn swap_impact_breakdown(
+        &self,
+        staked_token_amount: StakedTokenAmount,
+    ) -> Result<ImpactBreakdown, FixedPointError> {
+        let ideal_token_amount = self.marginal_price()?.value_of(&staked_token_amount)?;
+        let base_staked_token_value = self.calculate_staked_token_value(&staked_token_amount)?;
+        let staked_token_value = self.quote_swap_with_fee(&staked_token_amount)?.token_amount;
+
+        let curve_loss = (ideal_token_amount.0 - base_staked_token_value.0)?;
+        let fee_loss = (base_staked_token_value.0 - staked_token_value.0)?;
+
+        Ok(ImpactBreakdown {
+            fee_component: Percentage((fee_loss / ideal_token_amount.0)?),
+            curve_component: Percentage((curve_loss / ideal_token_amount.0)?),
+        })
+    }
+
+    /// Largest staked input whose total impact from `swap_impact_breakdown` stays within
+    /// `max_impact`, found by binary search over raw amounts the reserve can cover.
+    pub fn depth_at_impact(
+        &self,
+        max_impact: Percentage,
+    ) -> Result<StakedTokenAmount, FixedPointError> {
+        let mut low = 0;
+        let mut high = self.price.staked_for(&self.token_amount)?.0.raw();
+        while low < high {
+            let middle = low + (high - low).div_ceil(2);
+            let impact =
+                self.swap_impact_breakdown(StakedTokenAmount(FixedPointDecimal::from_raw(middle)))?;
+            if (impact.fee_component.0 + impact.curve_component.0)? <= max_impact.0 {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
         }
+
+        Ok(StakedTokenAmount(FixedPointDecimal::from_raw(low)))
     }
 
-    fn current_pool_value(&self) -> Result<TokenAmount, FixedPointError> {
-        let staked_token_value = self.calculate_staked_token_value(&self.staked_token_amount)?;
-        let current_liquidity = (self.token_amount.0 + staked_token_value.0)?;
-        Ok(TokenAmount(current_liquidity))
+    /// Smallest staked input whose swap pays out at least `token_out`. The fee depends on the
+    /// post-swap reserve, so the input is refined by bisection over raw amounts instead of
+    /// being solved in closed form.
+    pub fn exact_out_with_fee(
+        &self,
+        token_out: TokenAmount,
+    ) -> Result<StakedTokenAmount, FixedPointError> {
+        let mut low = 0;
+        let mut high = self.price.staked_for(&self.token_amount)?.0.raw();
+        let max_token_out = self
+            .quote_swap_with_fee(&StakedTokenAmount(FixedPointDecimal::from_raw(high)))?
+            .token_amount;
+        if max_token_out.0 < token_out.0 {
+            return Err(FixedPointError::Underflow);
+        }
+
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let quote =
+                self.quote_swap_with_fee(&StakedTokenAmount(FixedPointDecimal::from_raw(middle)))?;
+            if quote.token_amount.0 >= token_out.0 {
+                high = middle;
+            } else {
+                low = middle + 1;
+            }
+        }
+
+        Ok(StakedTokenAmount(FixedPointDecimal::from_raw(low)))
     }
 
-    fn calculate_staked_token_value(
+    pub fn quote_swap_at_price(
         &self,
-        staked_token_amount: &StakedTokenAmount,
+        staked_token_amount: StakedTokenAmount,
+        hypothetical_price: Price,
     ) -> Result<TokenAmount, FixedPointError> {
-        Ok(TokenAmount((self.price.0 * staked_token_amount.0)?))
+        let hypothetical_pool = LiquidityPool {
+            price: hypothetical_price,
+            ..self.clone()
+        };
+
+        Ok(hypothetical_pool
+            .quote_swap_with_fee(&staked_token_amount)?
+            .token_amount)
     }
 
-    fn apply_fee(
+    pub fn min_out_for_tolerance(
         &self,
-        token_amount: FixedPointDecimal,
-        fee: &Percentage,
-    ) -> Result<FixedPointDecimal, FixedPointError> {
-        let fee_value = (fee.0 * token_amount)?;
-        token_amount - fee_value
-    }
-}
+        staked_token_amount: StakedTokenAmount,
+        tolerance: Percentage,
+    ) -> Result<TokenAmount, FixedPointError> {
+        let quoted_token_amount = self.quote_swap_with_fee(&staked_token_amount)?.token_amount;
+        let kept_share = (FixedPointDecimal::ONE - tolerance.clamped().0)?;
 
-impl fmt::Display for LiquidityPool {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "> LiquidityPool")?;
-        writeln!(f, "\t const Price: {}", self.price.0)?;
-        writeln!(f, "\t const Min fee: {}", self.min_fee.0)?;
-        writeln!(f, "\t const Max fee: {}", self.max_fee.0)?;
-        writeln!(f, "\t const Target liquidity: {}", self.liquidity_target.0)?;
-        writeln!(f, "\t - Token amount: {}", self.token_amount.0)?;
-        writeln!(f, "\t - Liquidity token amount: {}", self.lp_token_amount.0)?;
-        writeln!(
-            f,
-            "\t - Staked token amount: {}",
-            self.staked_token_amount.0
-        )?;
-        Ok(())
+        Ok(TokenAmount(
+            quoted_token_amount
+                .0
+                .mul_div(kept_share, FixedPointDecimal::ONE)?,
+        ))
     }
-}
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    fn create_sut(
-        token_amount: TokenAmount,
+    pub fn swap_at(
+        &mut self,
         staked_token_amount: StakedTokenAmount,
-        lp_token_amount: LpTokenAmount,
-    ) -> LiquidityPool {
-        let price = Price(FixedPointDecimal::try_from(1.5).unwrap());
-        let min_fee = Percentage(FixedPointDecimal::try_from(0.001).unwrap());
-        let max_fee = Percentage(FixedPointDecimal::try_from(0.09).unwrap());
-        let liquidity_target = TokenAmount(FixedPointDecimal::try_from(90.0).unwrap());
+        timestamp: u64,
+    ) -> Result<TokenAmount, FixedPointError> {
+        let token_amount = self.swap(staked_token_amount)?;
+        self.record_swap_volume(TokenAmount(token_amount.0), timestamp);
+        Ok(token_amount)
+    }
 
-        LiquidityPool {
-            price,
-            token_amount,
-            staked_token_amount,
-            lp_token_amount,
-            liquidity_target,
-            min_fee,
-            max_fee,
+    pub fn record_swap_volume(&mut self, amount: TokenAmount, timestamp: u64) {
+        let retention_start = timestamp.saturating_sub(VOLUME_RETENTION_SECONDS);
+        while let Some((oldest_timestamp, _)) = self.swap_volume.front() {
+            if *oldest_timestamp >= retention_start {
+                break;
+            }
+            self.swap_volume.pop_front();
         }
-    }
 
-    mod add_liquidity {
-        use super::*;
+        self.swap_volume.push_back((timestamp, amount));
+    }
 
-        #[test]
-        fn calculates_lp_tokens_correctly_on_empty_pool() {
-            let mut sut = create_sut(
-                TokenAmount::default(),
-                StakedTokenAmount::default(),
-                LpTokenAmount::default(),
-            );
-            let lp_tokens = sut
-                .add_liquidity(TokenAmount(FixedPointDecimal::try_from(100).unwrap()))
-                .unwrap();
+    /// Sums the volume recorded by `swap_at` and `record_swap_volume`. Swaps made without a
+    /// timestamp, e.g. through plain `swap`, are not counted.
+    pub fn volume_since(&self, since_timestamp: u64) -> Result<TokenAmount, FixedPointError> {
+        let mut volume = FixedPointDecimal::default();
+        for (timestamp, amount) in &self.swap_volume {
+            if *timestamp >= since_timestamp {
+                volume = (volume + amount.0)?;
+            }
+        }
+        Ok(TokenAmount(volume))
+    }
 
-            assert_eq!(lp_tokens.0, 100);
-            assert_eq!(sut.token_amount.0, 100);
-            assert_eq!(sut.staked_token_amount.0, 0);
-            assert_eq!(sut.lp_token_amount.0, 100);
+    pub fn max_swap_at_min_fee(&self) -> Result<StakedTokenAmount, FixedPointError> {
+        if self.token_amount.0 <= self.liquidity_target.0 {
+            return Ok(StakedTokenAmount::default());
         }
 
-        #[test]
-        fn calculates_lp_tokens_correctly_on_non_empty_pool() {
-            let mut sut = create_sut(
-                TokenAmount(FixedPointDecimal::try_from(91.009).unwrap()),
-                StakedTokenAmount(FixedPointDecimal::try_from(6).unwrap()),
-                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
-            );
-            let lp_tokens = sut
-                .add_liquidity(TokenAmount(FixedPointDecimal::try_from(10).unwrap()))
-                .unwrap();
+        let liquidity_above_target = (self.token_amount.0 - self.liquidity_target.0)?;
+        self.price.staked_for(&TokenAmount(liquidity_above_target))
+    }
 
-            assert_eq!(lp_tokens.0, FixedPointDecimal::try_from(9.9991).unwrap());
-            assert_eq!(
-                sut.token_amount.0,
+    pub fn deposit_to_reach_target(&self) -> Result<TokenAmount, FixedPointError> {
+        Ok(TokenAmount(
+            self.liquidity_target.0.sub_or_zero(self.token_amount.0),
+        ))
+    }
+
+    pub fn undo(&mut self) -> Result<(), FixedPointError> {
+        let snapshot = self
+            .undo_stack
+            .pop_back()
+            .ok_or(FixedPointError::NothingToUndo)?;
+
+        self.token_amount = snapshot.token_amount;
+        self.staked_token_amount = snapshot.staked_token_amount;
+        self.lp_token_amount = snapshot.lp_token_amount;
+        self.escrowed_lp_token_amount = snapshot.escrowed_lp_token_amount;
+        self.pending_removals = snapshot.pending_removals;
+        self.next_ticket_id = snapshot.next_ticket_id;
+        self.protocol_treasury = snapshot.protocol_treasury;
+        self.net_principal = snapshot.net_principal;
+        self.swap_volume = snapshot.swap_volume;
+        self.rounding_drift = snapshot.rounding_drift;
+
+        Ok(())
+    }
+
+    /// Deposits, runs the swaps, then withdraws the minted LP tokens. Returns the value
+    /// withdrawn in base tokens, staked tokens valued at the pool price. The pool is
+    /// restored if any step fails.
+    pub fn simulate_lp_cycle(
+        &mut self,
+        deposit: TokenAmount,
+        intervening_swaps: &[StakedTokenAmount],
+    ) -> Result<TokenAmount, FixedPointError> {
+        let snapshot = self.clone();
+        let result = self
+            .with_single_undo_snapshot(|pool| pool.execute_lp_cycle(deposit, intervening_swaps));
+        if result.is_err() {
+            *self = snapshot;
+        }
+        result
+    }
+
+    /// Rejects fees above `ONE` with `InvalidPercentage`.
+    pub fn set_protocol_fee(&mut self, protocol_fee: Percentage) -> Result<(), FixedPointError> {
+        Self::validate_fee_config(Percentage::default(), protocol_fee.clone())?;
+
+        self.protocol_fee = protocol_fee;
+        Ok(())
+    }
+
+    pub fn protocol_treasury(&self) -> &TokenAmount {
+        &self.protocol_treasury
+    }
+
+    pub fn collect_protocol_fees(&mut self) -> TokenAmount {
+        std::mem::take(&mut self.protocol_treasury)
+    }
+
+    pub fn set_liquidity_target(&mut self, new_target: TokenAmount) -> Result<(), FixedPointError> {
+        if new_target.0 == FixedPointDecimal::default() {
+            return Err(FixedPointError::DivisionByZero);
+        }
+
+        self.liquidity_target = new_target;
+        Ok(())
+    }
+
+    /// Caps the LP fee charged on a single swap at an absolute amount; `None` removes the cap.
+    pub fn set_max_absolute_fee(&mut self, max_absolute_fee: Option<TokenAmount>) {
+        self.max_absolute_fee = max_absolute_fee;
+    }
+
+    /// Smallest deposit accepted while the pool is empty, guarding against share inflation.
+    pub fn set_minimum_first_deposit(&mut self, minimum_first_deposit: TokenAmount) {
+        self.minimum_first_deposit = minimum_first_deposit;
+    }
+
+    /// Rejects fees above `ONE` with `InvalidPercentage`.
+    pub fn set_flash_fee(&mut self, flash_fee: Percentage) -> Result<(), FixedPointError> {
+        Self::validate_fee_config(Percentage::default(), flash_fee.clone())?;
+
+        self.flash_fee = flash_fee;
+        Ok(())
+    }
+
+    pub fn flash_loan(
+        &mut self,
+        amount: TokenAmount,
+        f: impl FnOnce(&mut LiquidityPool) -> Result<TokenAmount, FixedPointError>,
+    ) -> Result<(), FixedPointError> {
+        let snapshot = self.clone();
+        let result = self.with_single_undo_snapshot(|pool| pool.execute_flash_loan(amount, f));
+        if result.is_err() {
+            *self = snapshot;
+        }
+        result
+    }
+
+    pub fn tvl(&self) -> Result<TokenAmount, FixedPointError> {
+        self.current_pool_value()
+    }
+
+    /// TVL above the net principal deposited by LPs, i.e. the swap and removal fees retained.
+    /// Price moves and donations since the deposits are counted here as well.
+    pub fn fee_accrued_value(&self) -> Result<TokenAmount, FixedPointError> {
+        Ok(TokenAmount(self.tvl()?.0.sub_or_zero(self.net_principal.0)))
+    }
+
+    /// An empty pool reports `ONE`, the rate at which the first deposit is minted.
+    pub fn lp_token_price(&self) -> Result<Price, FixedPointError> {
+        if self.is_empty() {
+            return Ok(Price(FixedPointDecimal::ONE));
+        }
+
+        Ok(Price((self.tvl()?.0 / self.lp_token_amount.0)?))
+    }
+
+    /// Change in LP token price a swap would cause.
+    pub fn lp_price_delta_of_swap(
+        &self,
+        staked_token_amount: StakedTokenAmount,
+    ) -> Result<FixedPointDecimal, FixedPointError> {
+        let mut simulated_pool = self.clone();
+        simulated_pool.swap(staked_token_amount)?;
+
+        simulated_pool.lp_token_price()?.0 - self.lp_token_price()?.0
+    }
+
+    /// LP tokens minted per unit of base-token value deposited; `ONE` for an empty pool.
+    pub fn lp_mint_rate(&self) -> Result<FixedPointDecimal, FixedPointError> {
+        if self.is_empty() {
+            return Ok(FixedPointDecimal::ONE);
+        }
+
+        self.lp_token_amount.0 / self.current_pool_value()?.0
+    }
+
+    /// Tokens and staked tokens backing one LP token.
+    pub fn lp_token_backing(&self) -> Result<(TokenAmount, StakedTokenAmount), FixedPointError> {
+        let token_amount_per_lp_token = (self.token_amount.0 / self.lp_token_amount.0)?;
+        let staked_token_amount_per_lp_token =
+            (self.staked_token_amount.0 / self.lp_token_amount.0)?;
+
+        Ok((
+            TokenAmount(token_amount_per_lp_token),
+            StakedTokenAmount(staked_token_amount_per_lp_token),
+        ))
+    }
+
+    /// Simple, non-compounding estimate of the yearly return from fees earned over a period.
+    pub fn estimated_apy(
+        &self,
+        fees_earned: TokenAmount,
+        period_seconds: u64,
+    ) -> Result<Percentage, FixedPointError> {
+        let period_yield = (fees_earned.0 / self.tvl()?.0)?;
+        let periods_per_year = (FixedPointDecimal::try_from(SECONDS_PER_YEAR)?
+            / FixedPointDecimal::try_from(period_seconds)?)?;
+
+        Ok(Percentage((period_yield * periods_per_year)?))
+    }
+
+    /// Returns `(token_share, staked_share)` of TVL. The staked share is taken as the
+    /// remainder so the two always sum to exactly one; an empty pool is `DivisionByZero`.
+    pub fn composition(&self) -> Result<(Percentage, Percentage), FixedPointError> {
+        let token_share = (self.token_amount.0 / self.tvl()?.0)?;
+        let staked_share = (FixedPointDecimal::ONE - token_share)?;
+
+        Ok((Percentage(token_share), Percentage(staked_share)))
+    }
+
+    /// Swap needed to move the token share of TVL to `target_token_share`, ignoring fees.
+    /// Returns `SwapDirection::None` when already within one basis point of the target.
+    pub fn rebalance_swap_to(
+        &self,
+        target_token_share: Percentage,
+    ) -> Result<SwapDirection, FixedPointError> {
+        let tvl = self.tvl()?;
+        let (token_share, _) = self.composition()?;
+        let share_difference = if token_share.0 > target_token_share.0 {
+            (token_share.0 - target_token_share.0)?
+        } else {
+            (target_token_share.0 - token_share.0)?
+        };
+        if share_difference <= REBALANCE_TOLERANCE {
+            return Ok(SwapDirection::None);
+        }
+
+        let target_token_amount = target_token_share.of(tvl.0)?;
+        if target_token_amount > tvl.0 {
+            return Err(FixedPointError::Underflow);
+        }
+
+        if self.token_amount.0 > target_token_amount {
+            let excess_token_amount = (self.token_amount.0 - target_token_amount)?;
+            Ok(SwapDirection::StakedIn(
+                self.price.staked_for(&TokenAmount(excess_token_amount))?,
+            ))
+        } else {
+            let missing_token_amount = (target_token_amount - self.token_amount.0)?;
+            Ok(SwapDirection::TokenIn(TokenAmount(missing_token_amount)))
+        }
+    }
+
+    pub fn effective_rate_with_cost(
+        &self,
+        staked_token_amount: StakedTokenAmount,
+        fixed_cost: TokenAmount,
+    ) -> Result<Price, FixedPointError> {
+        let token_amount = self.quote_swap_with_fee(&staked_token_amount)?.token_amount;
+        let net_token_amount = (token_amount.0 - fixed_cost.0)?;
+
+        Ok(Price((net_token_amount / staked_token_amount.0)?))
+    }
+
+    /// Forward-difference slope of the swap fee, per one whole staked token of extra size.
+    pub fn marginal_fee_rate(
+        &self,
+        at_staked_amount: StakedTokenAmount,
+    ) -> Result<Percentage, FixedPointError> {
+        let larger_staked_amount = (at_staked_amount.0 + FixedPointDecimal::ONE)?;
+        let fee = self.quote_swap_with_fee(&at_staked_amount)?.fee;
+        let larger_fee = self
+            .quote_swap_with_fee(&StakedTokenAmount(larger_staked_amount))?
+            .fee;
+
+        Ok(Percentage((larger_fee.0 - fee.0)?))
+    }
+
+    /// External staked-token price at which the after-fee swap output equals the input's
+    /// external value. Below this price swapping into the pool is profitable.
+    pub fn arb_break_even_price(
+        &self,
+        staked_token_amount: StakedTokenAmount,
+    ) -> Result<Price, FixedPointError> {
+        self.effective_rate_with_cost(staked_token_amount, TokenAmount::default())
+    }
+
+    /// Evaluates the fee at `samples` evenly spaced final liquidities from zero to 1.5 times
+    /// the liquidity target, inclusive.
+    pub fn fee_curve_samples(
+        &self,
+        samples: usize,
+    ) -> Result<Vec<(TokenAmount, Percentage)>, FixedPointError> {
+        let max_liquidity = ((self.liquidity_target.0 * 3)? / 2)?;
+        let intervals = samples.saturating_sub(1).max(1) as u64;
+
+        (0..samples as u64)
+            .map(|sample| {
+                let final_liquidity = TokenAmount(((max_liquidity * sample)? / intervals)?);
+                let fee = self.calculate_fee(final_liquidity.clone())?;
+                Ok((final_liquidity, fee))
+            })
+            .collect()
+    }
+
+    /// Smallest staked input that returns at least one raw unit of base token. Any smaller
+    /// swap is rounded away entirely.
+    pub fn min_profitable_swap(&self) -> Result<StakedTokenAmount, FixedPointError> {
+        let staked_token_amount =
+            StakedTokenAmount(FixedPointDecimal::from_raw(1).mul_div_rounded(
+                FixedPointDecimal::ONE,
+                self.price.0,
+                RoundingMode::Ceil,
+            )?);
+        if self.quote_swap_with_fee(&staked_token_amount)?.token_amount == TokenAmount::default() {
+            return Err(FixedPointError::InvalidInput);
+        }
+
+        Ok(staked_token_amount)
+    }
+
+    /// Recovers the fee rate applied to a swap from its pre-fee and after-fee outputs.
+    pub fn implied_fee(
+        gross_out: TokenAmount,
+        net_out: TokenAmount,
+    ) -> Result<Percentage, FixedPointError> {
+        Ok(Percentage(((gross_out.0 - net_out.0)? / gross_out.0)?))
+    }
+
+    /// Number of `typical_swap`s whose LP fees cover a loss of `il` of the pool's TVL, rounded
+    /// up. Fails with `DivisionByZero` if such a swap earns no fee.
+    pub fn swaps_to_breakeven(
+        &self,
+        il: Percentage,
+        typical_swap: StakedTokenAmount,
+    ) -> Result<u64, FixedPointError> {
+        let il_value = il.of(self.tvl()?.0)?;
+        let fee_per_swap = self.quote_swap_with_fee(&typical_swap)?.fee_amount.0;
+        if fee_per_swap == FixedPointDecimal::default() {
+            return Err(FixedPointError::DivisionByZero);
+        }
+
+        Ok(il_value.raw().div_ceil(fee_per_swap.raw()))
+    }
+
+    pub fn fee_earnings_for_share(
+        &self,
+        holder_lp: LpTokenAmount,
+        swaps: &[StakedTokenAmount],
+    ) -> Result<TokenAmount, FixedPointError> {
+        let mut simulated_pool = self.clone();
+        let mut total_fees = FixedPointDecimal::default();
+        for staked_token_amount in swaps {
+            let quote = simulated_pool.quote_swap_with_fee(staked_token_amount)?;
+            total_fees = (total_fees + quote.fee_amount.0)?;
+            simulated_pool.swap(staked_token_amount.clone())?;
+        }
+
+        Ok(TokenAmount(
+            total_fees.mul_div(holder_lp.0, self.lp_token_amount.0)?,
+        ))
+    }
+
+    pub fn is_solvent(&self) -> Result<bool, FixedPointError> {
+        let staked_token_value = self.price.value_of(&self.staked_token_amount)?;
+        Ok(self.token_amount.0 >= staked_token_value.0)
+    }
+
+    /// Renders the same fields as `Display` with padded labels and right-aligned values.
+    pub fn display_table(&self) -> String {
+        let rows = [
+            ("Price", &self.price.0),
+            ("Min fee", &self.min_fee.0),
+            ("Max fee", &self.max_fee.0),
+            ("Target liquidity", &self.liquidity_target.0),
+            ("Token amount", &self.token_amount.0),
+            ("Liquidity token amount", &self.lp_token_amount.0),
+            ("Staked token amount", &self.staked_token_amount.0),
+        ]
+        .map(|(label, value)| (label, value.to_trimmed_string()));
+
+        let label_width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        let value_width = rows.iter().map(|(_, value)| value.len()).max().unwrap_or(0);
+
+        rows.iter()
+            .map(|(label, value)| {
+                format!(
+                    "{:<label_width$}  {:>value_width$}\n",
+                    label,
+                    value,
+                    label_width = label_width,
+                    value_width = value_width
+                )
+            })
+            .collect()
+    }
+
+    /// Total absolute difference between the exact and the stored trader output over all swaps,
+    /// rounded up. Each swap contributes less than three units of the last decimal place.
+    pub fn rounding_drift(&self) -> FixedPointDecimal {
+        let drift = self
+            .rounding_drift
+            .div_ceil(FACTOR as u128 * FACTOR as u128);
+        FixedPointDecimal::from_raw(drift.min(u64::MAX as u128) as u64)
+    }
+
+    pub fn simulate_price_shock(
+        &self,
+        shock: Percentage,
+        direction: ShockDirection,
+    ) -> Result<PoolSnapshot, FixedPointError> {
+        let price_multiplier = match direction {
+            ShockDirection::Up => (FixedPointDecimal::ONE + shock.0)?,
+            ShockDirection::Down => (FixedPointDecimal::ONE - shock.clamped().0)?,
+        };
+        let shocked_pool = LiquidityPool {
+            price: self.price.scale_by(Percentage(price_multiplier))?,
+            ..self.clone()
+        };
+
+        let (token_share, staked_share) = shocked_pool.composition()?;
+        Ok(PoolSnapshot {
+            tvl: shocked_pool.tvl()?,
+            token_share,
+            staked_share,
+            is_solvent: shocked_pool.is_solvent()?,
+        })
+    }
+
+    /// Highest price at which `is_solvent` still holds. With no staked tokens the pool is
+    /// solvent at any price and `Price(FixedPointDecimal::MAX)` is returned.
+    pub fn solvency_price_limit(&self) -> Result<Price, FixedPointError> {
+        if self.staked_token_amount.0 == FixedPointDecimal::default() {
+            return Ok(Price(FixedPointDecimal::MAX));
+        }
+
+        Ok(Price((self.token_amount.0 / self.staked_token_amount.0)?))
+    }
+
+    /// Fixed-point multiplications and divisions done by the last successful swap,
+    /// `add_liquidity` or `remove_liquidity`.
+    #[cfg(feature = "instrument")]
+    pub fn last_op_cost(&self) -> OpCost {
+        self.last_op_cost
+    }
+
+    pub fn tokens_to_staked(
+        &self,
+        token_amount: TokenAmount,
+    ) -> Result<StakedTokenAmount, FixedPointError> {
+        self.price.staked_for(&token_amount)
+    }
+
+    pub fn marginal_price(&self) -> Result<Price, FixedPointError> {
+        Ok(Price(self.price.0))
+    }
+
+    #[cfg(feature = "instrument")]
+    fn record_op_cost(&mut self, counts_before: OpCost) {
+        self.last_op_cost = op_counts().since(counts_before);
+    }
+
+    /// Records one undo snapshot for a composite operation and suppresses the snapshots of
+    /// the operations it runs, so a single `undo` reverts all of it.
+    fn with_single_undo_snapshot<T>(
+        &mut self,
+        op: impl FnOnce(&mut LiquidityPool) -> Result<T, FixedPointError>,
+    ) -> Result<T, FixedPointError> {
+        self.push_undo_snapshot();
+        self.undo_suppression_depth += 1;
+        let result = op(self);
+        self.undo_suppression_depth -= 1;
+        result
+    }
+
+    fn push_undo_snapshot(&mut self) {
+        if self.undo_suppression_depth > 0 {
+            return;
+        }
+        if self.undo_stack.len() == MAX_UNDO_DEPTH {
+            self.undo_stack.pop_front();
+        }
+
+        self.undo_stack.push_back(BalanceSnapshot {
+            token_amount: self.token_amount.clone(),
+            staked_token_amount: self.staked_token_amount.clone(),
+            lp_token_amount: self.lp_token_amount.clone(),
+            escrowed_lp_token_amount: self.escrowed_lp_token_amount.clone(),
+            pending_removals: self.pending_removals.clone(),
+            next_ticket_id: self.next_ticket_id,
+            protocol_treasury: self.protocol_treasury.clone(),
+            net_principal: self.net_principal.clone(),
+            swap_volume: self.swap_volume.clone(),
+            rounding_drift: self.rounding_drift,
+        });
+    }
+
+    fn execute_claim_removal(
+        &mut self,
+        ticket: RemovalTicket,
+    ) -> Result<(TokenAmount, StakedTokenAmount), FixedPointError> {
+        let lp_token_amount = self
+            .pending_removals
+            .remove(&ticket.id)
+            .ok_or(FixedPointError::InvalidTicket)?;
+        self.escrowed_lp_token_amount.0 = (self.escrowed_lp_token_amount.0 - lp_token_amount.0)?;
+
+        self.remove_liquidity(lp_token_amount)
+    }
+
+    fn execute_single_sided_removal(
+        &mut self,
+        lp_token_amount: LpTokenAmount,
+    ) -> Result<TokenAmount, FixedPointError> {
+        let (token_amount, staked_token_amount) = self.remove_liquidity(lp_token_amount)?;
+        let swapped_token_amount = self.swap(staked_token_amount)?;
+
+        Ok(TokenAmount((token_amount.0 + swapped_token_amount.0)?))
+    }
+
+    fn execute_lp_cycle(
+        &mut self,
+        deposit: TokenAmount,
+        intervening_swaps: &[StakedTokenAmount],
+    ) -> Result<TokenAmount, FixedPointError> {
+        let minted_lp_token_amount = self.add_liquidity(deposit)?;
+        for staked_token_amount in intervening_swaps {
+            self.swap(staked_token_amount.clone())?;
+        }
+        let (token_amount, staked_token_amount) = self.remove_liquidity(minted_lp_token_amount)?;
+
+        let staked_token_value = self.price.value_of(&staked_token_amount)?;
+        Ok(TokenAmount((token_amount.0 + staked_token_value.0)?))
+    }
+
+    fn execute_flash_loan(
+        &mut self,
+        amount: TokenAmount,
+        f: impl FnOnce(&mut LiquidityPool) -> Result<TokenAmount, FixedPointError>,
+    ) -> Result<(), FixedPointError> {
+        let flash_fee_value = self.flash_fee.of(amount.0)?;
+        let required_repayment = (amount.0 + flash_fee_value)?;
+
+        self.token_amount.0 = (self.token_amount.0 - amount.0)?;
+        let repayment = f(self)?;
+        if repayment.0 < required_repayment {
+            return Err(FixedPointError::InsufficientRepayment);
+        }
+        self.token_amount.0 = (self.token_amount.0 + repayment.0)?;
+
+        Ok(())
+    }
+
+    fn quote_swap_with_fee(
+        &self,
+        staked_token_amount: &StakedTokenAmount,
+    ) -> Result<SwapQuote, FixedPointError> {
+        let base_staked_token_value = self.calculate_staked_token_value(staked_token_amount)?;
+        let final_token_amount = (self.token_amount.0 - base_staked_token_value.0)?;
+
+        let fee = self.calculate_fee(TokenAmount(final_token_amount))?;
+        let mut fee_amount =
+            (base_staked_token_value.0 - self.apply_fee(base_staked_token_value.0, &fee)?)?;
+        if let Some(max_absolute_fee) = &self.max_absolute_fee {
+            fee_amount = fee_amount.min(max_absolute_fee.0);
+        }
+        let staked_token_value = (base_staked_token_value.0 - fee_amount)?;
+        let protocol_fee_amount = self.protocol_fee.of(base_staked_token_value.0)?;
+
+        Ok(SwapQuote {
+            token_amount: TokenAmount((staked_token_value - protocol_fee_amount)?),
+            fee,
+            fee_amount: TokenAmount(fee_amount),
+            protocol_fee_amount: TokenAmount(protocol_fee_amount),
+        })
+    }
+
+    /// Error of the quoted trader output against the exact rational result, in units of
+    /// `FACTOR^-3`.
+    fn swap_rounding_error(
+        &self,
+        staked_token_amount: &StakedTokenAmount,
+        quote: &SwapQuote,
+    ) -> u128 {
+        let gross_value = staked_token_amount.0.raw() as u128 * self.price.0.raw() as u128;
+        let mut ideal_fee = gross_value * quote.fee.clamped().0.raw() as u128;
+        if let Some(max_absolute_fee) = &self.max_absolute_fee {
+            ideal_fee =
+                ideal_fee.min(max_absolute_fee.0.raw() as u128 * FACTOR as u128 * FACTOR as u128);
+        }
+        let ideal_protocol_fee = gross_value * self.protocol_fee.0.raw() as u128;
+        let ideal_output = (gross_value * FACTOR as u128)
+            .saturating_sub(ideal_fee)
+            .saturating_sub(ideal_protocol_fee);
+        let actual_output = quote.token_amount.0.raw() as u128 * FACTOR as u128 * FACTOR as u128;
+
+        ideal_output.abs_diff(actual_output)
+    }
+
+    fn calculate_fee(&self, final_liquidity: TokenAmount) -> Result<Percentage, FixedPointError> {
+        if final_liquidity.0 >= self.liquidity_target.0 {
+            Ok(Percentage(self.min_fee.0))
+        } else {
+            let max_min_fee_difference = (self.max_fee.0 - self.min_fee.0)?;
+            let fee_discount =
+                max_min_fee_difference.mul_div(final_liquidity.0, self.liquidity_target.0)?;
+            let fee = (self.max_fee.0 - fee_discount)?;
+
+            Ok(Percentage(fee))
+        }
+    }
+
+    fn current_pool_value(&self) -> Result<TokenAmount, FixedPointError> {
+        let staked_token_value =
+            self.price.0.raw() as u128 * self.staked_token_amount.0.raw() as u128 / FACTOR as u128;
+        let current_liquidity = staked_token_value + self.token_amount.0.raw() as u128;
+        if current_liquidity > u64::MAX as u128 {
+            return Err(FixedPointError::Overflow);
+        }
+
+        Ok(TokenAmount(FixedPointDecimal::from_raw(
+            current_liquidity as u64,
+        )))
+    }
+
+    fn calculate_staked_token_value(
+        &self,
+        staked_token_amount: &StakedTokenAmount,
+    ) -> Result<TokenAmount, FixedPointError> {
+        self.price.value_of(staked_token_amount)
+    }
+
+    fn apply_fee(
+        &self,
+        token_amount: FixedPointDecimal,
+        fee: &Percentage,
+    ) -> Result<FixedPointDecimal, FixedPointError> {
+        let fee_value = fee.clamped().of(token_amount)?;
+        token_amount - fee_value
+    }
+}
+
+/// Swaps `input` through `pool_a`, then swaps the output through `pool_b`.
+/// The base token of `pool_a` must be the staked token of `pool_b`. Both pools are
+/// restored if either hop fails.
+pub fn route_swap(
+    pool_a: &mut LiquidityPool,
+    pool_b: &mut LiquidityPool,
+    input: StakedTokenAmount,
+) -> Result<TokenAmount, FixedPointError> {
+    let snapshot_a = pool_a.clone();
+    let snapshot_b = pool_b.clone();
+
+    let result = pool_a
+        .swap(input)
+        .and_then(|intermediate| pool_b.swap(StakedTokenAmount(intermediate.0)));
+    if result.is_err() {
+        *pool_a = snapshot_a;
+        *pool_b = snapshot_b;
+    }
+    result
+}
+
+/// Quotes `input` on every pool and returns the index and output of the best one. Pools that
+/// cannot fill the swap are skipped; if none can, the last quote error is returned.
+pub fn best_venue(
+    pools: &[LiquidityPool],
+    input: StakedTokenAmount,
+) -> Result<(usize, TokenAmount), FixedPointError> {
+    let mut best: Option<(usize, TokenAmount)> = None;
+    let mut last_error = FixedPointError::InvalidInput;
+    for (index, pool) in pools.iter().enumerate() {
+        match pool.quote_swap_with_fee(&input) {
+            Ok(quote) => {
+                if best
+                    .as_ref()
+                    .is_none_or(|(_, token_amount)| quote.token_amount.0 > token_amount.0)
+                {
+                    best = Some((index, quote.token_amount));
+                }
+            }
+            Err(error) => last_error = error,
+        }
+    }
+
+    best.ok_or(last_error)
+}
+
+impl fmt::Display for LiquidityPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "> LiquidityPool")?;
+        writeln!(f, "\t const Price: {}", self.price.0)?;
+        writeln!(f, "\t const Min fee: {}", self.min_fee.0)?;
+        writeln!(f, "\t const Max fee: {}", self.max_fee.0)?;
+        writeln!(f, "\t const Target liquidity: {}", self.liquidity_target.0)?;
+        writeln!(f, "\t - Token amount: {}", self.token_amount.0)?;
+        writeln!(f, "\t - Liquidity token amount: {}", self.lp_token_amount.0)?;
+        writeln!(
+            f,
+            "\t - Staked token amount: {}",
+            self.staked_token_amount.0
+        )?;
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_sut(
+        token_amount: TokenAmount,
+        staked_token_amount: StakedTokenAmount,
+        lp_token_amount: LpTokenAmount,
+    ) -> LiquidityPool {
+        let price = Price(FixedPointDecimal::try_from(1.5).unwrap());
+        let min_fee = Percentage(FixedPointDecimal::try_from(0.001).unwrap());
+        let max_fee = Percentage(FixedPointDecimal::try_from(0.09).unwrap());
+        let liquidity_target = TokenAmount(FixedPointDecimal::try_from(90.0).unwrap());
+
+        LiquidityPool {
+            price,
+            token_amount,
+            staked_token_amount,
+            lp_token_amount,
+            liquidity_target,
+            min_fee,
+            max_fee,
+            escrowed_lp_token_amount: LpTokenAmount::default(),
+            pending_removals: HashMap::new(),
+            next_ticket_id: 0,
+            swap_volume: VecDeque::new(),
+            flash_fee: Percentage::default(),
+            undo_stack: VecDeque::new(),
+            undo_suppression_depth: 0,
+            protocol_fee: Percentage::default(),
+            protocol_treasury: TokenAmount::default(),
+            rounding_drift: 0,
+            max_absolute_fee: None,
+            minimum_first_deposit: TokenAmount::default(),
+            net_principal: TokenAmount::default(),
+            #[cfg(feature = "instrument")]
+            last_op_cost: OpCost::default(),
+        }
+    }
+
+    mod add_liquidity {
+        use super::*;
+
+        #[test]
+        fn calculates_lp_tokens_correctly_on_empty_pool() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let lp_tokens = sut
+                .add_liquidity(TokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                .unwrap();
+
+            assert_eq!(lp_tokens.0, 100);
+            assert_eq!(sut.token_amount.0, 100);
+            assert_eq!(sut.staked_token_amount.0, 0);
+            assert_eq!(sut.lp_token_amount.0, 100);
+        }
+
+        #[test]
+        fn calculates_lp_tokens_correctly_on_non_empty_pool() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(91.009).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(6).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let lp_tokens = sut
+                .add_liquidity(TokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            assert_eq!(lp_tokens.0, FixedPointDecimal::try_from(9.9991).unwrap());
+            assert_eq!(
+                sut.token_amount.0,
+                FixedPointDecimal::try_from(101.009).unwrap()
+            );
+            assert_eq!(sut.staked_token_amount.0, 6);
+            assert_eq!(
+                sut.lp_token_amount.0,
+                FixedPointDecimal::try_from(109.9991).unwrap()
+            );
+        }
+
+        #[test]
+        fn mints_exactly_at_large_lp_supply() {
+            let token_amount = FixedPointDecimal::try_from(300_000_000_000u64).unwrap();
+            let lp_token_amount = FixedPointDecimal::try_from(1_000_000_000_000u64).unwrap();
+            let deposit = FixedPointDecimal::try_from(300_000u64).unwrap();
+            let mut sut = create_sut(
+                TokenAmount(token_amount),
+                StakedTokenAmount::default(),
+                LpTokenAmount(lp_token_amount),
+            );
+
+            let lp_tokens = sut.add_liquidity(TokenAmount(deposit)).unwrap();
+
+            // Rounding the ratio 3.333333(3) first would mint 999999.9 LP tokens
+            let two_step_lp_tokens = (deposit * (lp_token_amount / token_amount).unwrap()).unwrap();
+            assert_eq!(
+                two_step_lp_tokens,
+                FixedPointDecimal::try_from(999_999.9).unwrap()
+            );
+            assert_eq!(lp_tokens.0, 1_000_000);
+        }
+    }
+
+    mod add_liquidity_overflow {
+        use super::*;
+
+        #[test]
+        fn returns_overflow_when_reserve_would_exceed_max() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::from_raw(u64::MAX - 1)),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let result = sut.add_liquidity(TokenAmount(FixedPointDecimal::from_raw(2)));
+
+            assert_eq!(result.err().unwrap(), FixedPointError::Overflow);
+            assert_eq!(
+                sut.token_amount.0,
+                FixedPointDecimal::from_raw(u64::MAX - 1)
+            );
+            assert_eq!(sut.lp_token_amount.0, 100);
+        }
+
+        #[test]
+        fn returns_overflow_when_minted_amount_would_exceed_max() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::from_raw(1)),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::from_raw(u64::MAX / 2)),
+            );
+            let result = sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(1).unwrap()));
+
+            assert_eq!(result.err().unwrap(), FixedPointError::Overflow);
+            assert_eq!(sut.token_amount.0, FixedPointDecimal::from_raw(1));
+        }
+
+        #[test]
+        fn leaves_state_unchanged_when_lp_supply_would_overflow() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::from_raw(u64::MAX - FACTOR)),
+            );
+            let result = sut.add_liquidity(TokenAmount(FixedPointDecimal::from_raw(1)));
+
+            assert_eq!(result.err().unwrap(), FixedPointError::Overflow);
+            assert_eq!(sut.token_amount.0, 1);
+        }
+    }
+
+    mod swap {
+        use super::*;
+
+        #[test]
+        fn should_swap_with_min_fee() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let tokens = sut
+                .swap(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            assert_eq!(tokens.0, FixedPointDecimal::try_from(14.985).unwrap());
+            assert_eq!(
+                sut.token_amount.0,
+                FixedPointDecimal::try_from(985.015).unwrap()
+            );
+            assert_eq!(sut.staked_token_amount.0, 10);
+        }
+
+        #[test]
+        fn should_swap_with_max_fee() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(15).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let tokens = sut
+                .swap(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            assert_eq!(tokens.0, FixedPointDecimal::try_from(13.65).unwrap());
+            assert_eq!(
+                sut.token_amount.0,
+                FixedPointDecimal::try_from(1.35).unwrap()
+            );
+            assert_eq!(sut.staked_token_amount.0, 10);
+        }
+    }
+
+    mod removal_queue {
+        use super::*;
+
+        #[test]
+        fn claims_queued_removal_at_current_state() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let ticket = sut
+                .request_removal(LpTokenAmount(FixedPointDecimal::try_from(50).unwrap()))
+                .unwrap();
+
+            assert_eq!(sut.escrowed_lp_token_amount.0, 50);
+            assert_eq!(sut.lp_token_amount.0, 100);
+
+            let (tokens, staked_tokens) = sut.claim_removal(ticket).unwrap();
+
+            assert_eq!(tokens.0, FixedPointDecimal::try_from(499.5).unwrap());
+            assert_eq!(staked_tokens.0, 0);
+            assert_eq!(sut.escrowed_lp_token_amount.0, 0);
+            assert_eq!(sut.lp_token_amount.0, 50);
+        }
+
+        #[test]
+        fn rejects_escrowing_more_than_supply() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            sut.request_removal(LpTokenAmount(FixedPointDecimal::try_from(60).unwrap()))
+                .unwrap();
+            let result =
+                sut.request_removal(LpTokenAmount(FixedPointDecimal::try_from(60).unwrap()));
+
+            assert_eq!(result.err().unwrap(), FixedPointError::Underflow);
+            assert_eq!(sut.escrowed_lp_token_amount.0, 60);
+        }
+
+        #[test]
+        fn direct_removal_cannot_spend_escrowed_tokens() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            sut.request_removal(LpTokenAmount(FixedPointDecimal::try_from(60).unwrap()))
+                .unwrap();
+            let result =
+                sut.remove_liquidity(LpTokenAmount(FixedPointDecimal::try_from(50).unwrap()));
+
+            assert_eq!(result.err().unwrap(), FixedPointError::Underflow);
+            assert_eq!(sut.lp_token_amount.0, 100);
+        }
+
+        #[test]
+        fn rejects_invalid_ticket() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let result = sut.claim_removal(RemovalTicket { id: 42 });
+
+            assert_eq!(result.err().unwrap(), FixedPointError::InvalidTicket);
+            assert_eq!(sut.lp_token_amount.0, 100);
+        }
+
+        #[test]
+        fn failed_claim_keeps_ticket_pending() {
+            let valid_pool = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            // A max fee below the min fee makes the removal fee underflow
+            let mut sut = LiquidityPool {
+                max_fee: Percentage::default(),
+                liquidity_target: TokenAmount(FixedPointDecimal::MAX),
+                ..valid_pool.clone()
+            };
+            let ticket = sut
+                .request_removal(LpTokenAmount(FixedPointDecimal::try_from(50).unwrap()))
+                .unwrap();
+            let ticket_id = ticket.id;
+
+            let result = sut.claim_removal(ticket);
+
+            assert_eq!(result.err().unwrap(), FixedPointError::Underflow);
+            assert_eq!(sut.escrowed_lp_token_amount.0, 50);
+            assert_eq!(sut.lp_token_amount.0, 100);
+
+            sut.max_fee = valid_pool.max_fee;
+            sut.liquidity_target = valid_pool.liquidity_target;
+            let (tokens, staked_tokens) =
+                sut.claim_removal(RemovalTicket { id: ticket_id }).unwrap();
+
+            assert_eq!(tokens.0, FixedPointDecimal::try_from(499.5).unwrap());
+            assert_eq!(staked_tokens.0, FixedPointDecimal::try_from(4.995).unwrap());
+            assert_eq!(sut.escrowed_lp_token_amount.0, 0);
+        }
+    }
+
+    mod marginal_price {
+        use super::*;
+
+        #[test]
+        fn equals_stored_price_for_fixed_price_model() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            assert_eq!(
+                sut.marginal_price().unwrap().0,
+                FixedPointDecimal::try_from(1.5).unwrap()
+            );
+
+            sut.swap(StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                .unwrap();
+            assert_eq!(
+                sut.marginal_price().unwrap().0,
+                FixedPointDecimal::try_from(1.5).unwrap()
+            );
+        }
+    }
+
+    mod swap_volume {
+        use super::*;
+
+        #[test]
+        fn sums_swaps_within_window() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.swap_at(
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                100,
+            )
+            .unwrap();
+            sut.swap_at(
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                200,
+            )
+            .unwrap();
+            sut.swap_at(
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                300,
+            )
+            .unwrap();
+
+            assert_eq!(
+                sut.volume_since(200).unwrap().0,
+                FixedPointDecimal::try_from(29.97).unwrap()
+            );
+            assert_eq!(
+                sut.volume_since(0).unwrap().0,
+                FixedPointDecimal::try_from(44.955).unwrap()
+            );
+            assert_eq!(sut.volume_since(301).unwrap().0, 0);
+        }
+
+        #[test]
+        fn prunes_entries_older_than_retention() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.record_swap_volume(TokenAmount(FixedPointDecimal::try_from(5).unwrap()), 0);
+            sut.record_swap_volume(
+                TokenAmount(FixedPointDecimal::try_from(7).unwrap()),
+                VOLUME_RETENTION_SECONDS + 1,
+            );
+
+            assert_eq!(sut.swap_volume.len(), 1);
+            assert_eq!(sut.volume_since(0).unwrap().0, 7);
+        }
+
+        #[test]
+        fn undo_drops_volume_of_reverted_swap() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.swap_at(
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                100,
+            )
+            .unwrap();
+            sut.swap_at(
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                200,
+            )
+            .unwrap();
+
+            sut.undo().unwrap();
+
+            assert_eq!(
+                sut.volume_since(0).unwrap().0,
+                FixedPointDecimal::try_from(14.985).unwrap()
+            );
+        }
+    }
+
+    mod quote_remove_liquidity {
+        use super::*;
+
+        #[test]
+        fn matches_actual_removal() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(101.009).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(6).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(109.9991).unwrap()),
+            );
+            let (quoted_tokens, quoted_staked_tokens, fee) = sut
+                .quote_remove_liquidity(LpTokenAmount(FixedPointDecimal::try_from(50).unwrap()))
+                .unwrap();
+
+            assert!(fee.0 > FixedPointDecimal::try_from(0.001).unwrap());
+            assert_eq!(
+                sut.token_amount.0,
                 FixedPointDecimal::try_from(101.009).unwrap()
             );
-            assert_eq!(sut.staked_token_amount.0, 6);
+            assert_eq!(sut.staked_token_amount.0, 6);
+            assert_eq!(
+                sut.lp_token_amount.0,
+                FixedPointDecimal::try_from(109.9991).unwrap()
+            );
+
+            let (tokens, staked_tokens) = sut
+                .remove_liquidity(LpTokenAmount(FixedPointDecimal::try_from(50).unwrap()))
+                .unwrap();
+
+            assert_eq!(tokens, quoted_tokens);
+            assert_eq!(staked_tokens, quoted_staked_tokens);
+        }
+
+        #[test]
+        fn reports_min_fee_above_target() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let (tokens, staked_tokens, fee) = sut
+                .quote_remove_liquidity(LpTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            assert_eq!(tokens.0, FixedPointDecimal::try_from(99.9).unwrap());
+            assert_eq!(staked_tokens.0, 0);
+            assert_eq!(fee.0, FixedPointDecimal::try_from(0.001).unwrap());
+        }
+    }
+
+    mod apply_fee {
+        use super::*;
+
+        #[test]
+        fn clamps_fee_above_one_to_zero_payout() {
+            let sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let payout = sut
+                .apply_fee(
+                    FixedPointDecimal::try_from(10).unwrap(),
+                    &Percentage(FixedPointDecimal::try_from(1.5).unwrap()),
+                )
+                .unwrap();
+
+            assert_eq!(payout, 0);
+        }
+
+        #[test]
+        fn never_pays_out_more_than_input() {
+            let sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let token_amount = FixedPointDecimal::try_from(10).unwrap();
+            let payout = sut
+                .apply_fee(token_amount, &Percentage(FixedPointDecimal::default()))
+                .unwrap();
+
+            assert_eq!(payout, token_amount);
+        }
+    }
+
+    mod max_swap_at_min_fee {
+        use super::*;
+
+        #[test]
+        fn boundary_swap_gets_min_fee() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(240).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let max_swap = sut.max_swap_at_min_fee().unwrap();
+            assert_eq!(max_swap.0, 100);
+
+            let tokens = sut.swap(max_swap).unwrap();
+            assert_eq!(tokens.0, FixedPointDecimal::try_from(149.85).unwrap());
+        }
+
+        #[test]
+        fn larger_swap_pays_more_than_min_fee() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(240).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let tokens = sut
+                .swap(StakedTokenAmount(
+                    FixedPointDecimal::try_from(100.000001).unwrap(),
+                ))
+                .unwrap();
+
+            assert!(tokens.0 < FixedPointDecimal::try_from(149.85).unwrap());
+        }
+
+        #[test]
+        fn is_zero_at_or_below_target() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(90).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            assert_eq!(sut.max_swap_at_min_fee().unwrap().0, 0);
+        }
+    }
+
+    mod swap_impact_breakdown {
+        use super::*;
+
+        #[test]
+        fn components_sum_to_total_slippage() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let staked_token_amount = StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap());
+            let ideal_token_amount = sut
+                .calculate_staked_token_value(&staked_token_amount)
+                .unwrap();
+            let tokens = sut
+                .quote_swap_with_fee(&staked_token_amount)
+                .unwrap()
+                .token_amount;
+            let total_slippage =
+                (((ideal_token_amount.0 - tokens.0).unwrap()) / ideal_token_amount.0).unwrap();
+
+            let breakdown = sut.swap_impact_breakdown(staked_token_amount).unwrap();
+
+            assert_eq!(
+                (breakdown.fee_component.0 + breakdown.curve_component.0).unwrap(),
+                total_slippage
+            );
+            assert_eq!(
+                breakdown.fee_component.0,
+                FixedPointDecimal::try_from(0.005945).unwrap()
+            );
+        }
+
+        #[test]
+        fn curve_component_is_zero_for_fixed_price_model() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let breakdown = sut
+                .swap_impact_breakdown(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            assert_eq!(breakdown.curve_component.0, 0);
+            assert_eq!(
+                breakdown.fee_component.0,
+                FixedPointDecimal::try_from(0.001).unwrap()
+            );
+        }
+    }
+
+    mod lp_token_price {
+        use super::*;
+
+        #[test]
+        fn is_one_on_empty_pool() {
+            let sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            assert_eq!(sut.lp_token_price().unwrap().0, 1);
+        }
+
+        #[test]
+        fn is_one_on_fresh_pool() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                .unwrap();
+
+            assert_eq!(sut.lp_token_price().unwrap().0, 1);
+        }
+
+        #[test]
+        fn grows_after_fee_earning_swap() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                .unwrap();
+            sut.swap(StakedTokenAmount(FixedPointDecimal::try_from(6).unwrap()))
+                .unwrap();
+
+            assert_eq!(
+                sut.lp_token_price().unwrap().0,
+                FixedPointDecimal::try_from(1.00009).unwrap()
+            );
+        }
+    }
+
+    mod flash_loan {
+        use super::*;
+
+        #[test]
+        fn keeps_repayment_and_fee() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            sut.set_flash_fee(Percentage(FixedPointDecimal::try_from(0.001).unwrap()))
+                .unwrap();
+
+            sut.flash_loan(
+                TokenAmount(FixedPointDecimal::try_from(500).unwrap()),
+                |pool| {
+                    assert_eq!(pool.token_amount.0, 500);
+                    Ok(TokenAmount(FixedPointDecimal::try_from(500.5).unwrap()))
+                },
+            )
+            .unwrap();
+
+            assert_eq!(
+                sut.token_amount.0,
+                FixedPointDecimal::try_from(1000.5).unwrap()
+            );
+        }
+
+        #[test]
+        fn rolls_back_on_insufficient_repayment() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            sut.set_flash_fee(Percentage(FixedPointDecimal::try_from(0.001).unwrap()))
+                .unwrap();
+
+            let result = sut.flash_loan(
+                TokenAmount(FixedPointDecimal::try_from(500).unwrap()),
+                |pool| {
+                    pool.swap(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))?;
+                    Ok(TokenAmount(FixedPointDecimal::try_from(500).unwrap()))
+                },
+            );
+
+            assert_eq!(
+                result.err().unwrap(),
+                FixedPointError::InsufficientRepayment
+            );
+            assert_eq!(sut.token_amount.0, 1000);
+            assert_eq!(sut.staked_token_amount.0, 0);
+        }
+    }
+
+    mod estimated_apy {
+        use super::*;
+
+        #[test]
+        fn annualizes_daily_fees() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let apy = sut
+                .estimated_apy(
+                    TokenAmount(FixedPointDecimal::try_from(1).unwrap()),
+                    24 * 60 * 60,
+                )
+                .unwrap();
+
+            assert_eq!(apy.0, FixedPointDecimal::try_from(0.365).unwrap());
+        }
+
+        #[test]
+        fn errors_on_empty_pool() {
+            let sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let result = sut.estimated_apy(
+                TokenAmount(FixedPointDecimal::try_from(1).unwrap()),
+                24 * 60 * 60,
+            );
+
+            assert_eq!(result.err().unwrap(), FixedPointError::DivisionByZero);
+        }
+
+        #[test]
+        fn errors_on_zero_period() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let result = sut.estimated_apy(TokenAmount(FixedPointDecimal::try_from(1).unwrap()), 0);
+
+            assert_eq!(result.err().unwrap(), FixedPointError::DivisionByZero);
+        }
+    }
+
+    mod random_scenario {
+        use super::*;
+
+        const MAX_FEE_SEED: u64 = 210;
+
+        #[derive(Debug, PartialEq)]
+        enum PoolOp {
+            AddLiquidity(u64),
+            Swap(u64),
+            RemoveLiquidityPermille(u64),
+        }
+
+        fn next_random(state: &mut u64) -> u64 {
+            *state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            *state >> 33
+        }
+
+        fn random_scenario(seed: u64, steps: usize) -> Vec<PoolOp> {
+            let mut state = seed;
+            (0..steps)
+                .map(|_| match next_random(&mut state) % 3 {
+                    0 => PoolOp::AddLiquidity(1 + next_random(&mut state) % 200),
+                    1 => PoolOp::Swap(1 + next_random(&mut state) % 50),
+                    _ => PoolOp::RemoveLiquidityPermille(1 + next_random(&mut state) % 500),
+                })
+                .collect()
+        }
+
+        fn run_scenario(seed: u64, steps: usize) -> bool {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let mut charged_max_fee = false;
+
+            for op in random_scenario(seed, steps) {
+                let before = sut.clone();
+                let result = match op {
+                    PoolOp::AddLiquidity(amount) => sut
+                        .add_liquidity(TokenAmount(FixedPointDecimal::try_from(amount).unwrap()))
+                        .map(|_| ()),
+                    PoolOp::Swap(amount) => {
+                        let staked_token_amount =
+                            StakedTokenAmount(FixedPointDecimal::try_from(amount).unwrap());
+                        let quoted_max_fee = sut
+                            .quote_swap_with_fee(&staked_token_amount)
+                            .is_ok_and(|quote| quote.fee.0 == sut.max_fee.0);
+                        let ideal_token_amount = sut
+                            .calculate_staked_token_value(&staked_token_amount)
+                            .unwrap();
+                        sut.swap(staked_token_amount).map(|tokens| {
+                            assert!(tokens.0 <= ideal_token_amount.0);
+                            charged_max_fee |= quoted_max_fee;
+                        })
+                    }
+                    PoolOp::RemoveLiquidityPermille(permille) => {
+                        let share = FixedPointDecimal::from_raw(permille * FACTOR / 1000);
+                        let lp_token_amount = (sut.lp_token_amount.0 * share).unwrap();
+                        sut.remove_liquidity(LpTokenAmount(lp_token_amount)).map(
+                            |(tokens, staked_tokens)| {
+                                assert!(tokens.0 <= before.token_amount.0);
+                                assert!(staked_tokens.0 <= before.staked_token_amount.0);
+                            },
+                        )
+                    }
+                };
+
+                if result.is_err() {
+                    assert_eq!(sut.token_amount, before.token_amount);
+                    assert_eq!(sut.staked_token_amount, before.staked_token_amount);
+                    assert_eq!(sut.lp_token_amount, before.lp_token_amount);
+                }
+            }
+
+            charged_max_fee
+        }
+
+        #[test]
+        fn same_seed_produces_same_scenario() {
+            assert_eq!(random_scenario(7, 50), random_scenario(7, 50));
+            assert_ne!(random_scenario(7, 50), random_scenario(8, 50));
+        }
+
+        #[test]
+        fn invariants_hold_across_seeds() {
+            for seed in 0..20 {
+                run_scenario(seed, 200);
+            }
+        }
+
+        #[test]
+        fn seed_reaches_max_fee_region() {
+            assert!(run_scenario(MAX_FEE_SEED, 200));
+        }
+    }
+
+    mod current_pool_value {
+        use super::*;
+
+        #[test]
+        fn handles_large_price_and_staked_amount() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1_000_000u64).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(4_000_000_000u64).unwrap()),
+                LpTokenAmount::default(),
+            );
+            sut.price = Price(FixedPointDecimal::try_from(4_000u64).unwrap());
+
+            assert_eq!(sut.current_pool_value().unwrap().0, 16_000_001_000_000u64);
+        }
+
+        #[test]
+        fn returns_overflow_when_value_exceeds_max() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::from_raw(u64::MAX)),
+                StakedTokenAmount(FixedPointDecimal::try_from(1u64).unwrap()),
+                LpTokenAmount::default(),
+            );
+            sut.price = Price(FixedPointDecimal::from_raw(1));
+
+            assert_eq!(
+                sut.current_pool_value().err().unwrap(),
+                FixedPointError::Overflow
+            );
+        }
+    }
+
+    mod calculate_fee {
+        use super::*;
+
+        #[test]
+        fn sloped_formula_meets_min_fee_at_target() {
+            let sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let max_min_fee_difference = (sut.max_fee.0 - sut.min_fee.0).unwrap();
+            let ratio_at_target = (sut.liquidity_target.0 / sut.liquidity_target.0).unwrap();
+            let sloped_fee_at_target =
+                (sut.max_fee.0 - (max_min_fee_difference * ratio_at_target).unwrap()).unwrap();
+
+            assert_eq!(sloped_fee_at_target, sut.min_fee.0);
+            assert_eq!(
+                sut.calculate_fee(TokenAmount(sut.liquidity_target.0))
+                    .unwrap()
+                    .0,
+                sut.min_fee.0
+            );
+        }
+
+        #[test]
+        fn interpolates_with_single_rounding() {
+            let sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let fee = sut
+                .calculate_fee(TokenAmount(FixedPointDecimal::from_raw(24_456_773)))
+                .unwrap();
+
+            // 0.09 - 0.089 * 24.456773 / 90 = 0.0658149689..., while rounding the
+            // ratio first would give 0.065816.
+            assert_eq!(fee.0, FixedPointDecimal::try_from(0.065815).unwrap());
+        }
+
+        #[test]
+        fn swap_landing_on_target_pays_min_fee() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(240).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let tokens = sut
+                .swap(StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                .unwrap();
+
+            assert_eq!(
+                sut.token_amount.0,
+                FixedPointDecimal::try_from(90.15).unwrap()
+            );
+            assert_eq!(tokens.0, FixedPointDecimal::try_from(149.85).unwrap());
+        }
+    }
+
+    mod composition {
+        use super::*;
+
+        #[test]
+        fn splits_balanced_pool_evenly() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+            let (token_share, staked_share) = sut.composition().unwrap();
+
+            assert_eq!(token_share.0, FixedPointDecimal::try_from(0.5).unwrap());
+            assert_eq!(staked_share.0, FixedPointDecimal::try_from(0.5).unwrap());
+        }
+
+        #[test]
+        fn splits_imbalanced_pool() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(450).unwrap()),
+            );
+            let (token_share, staked_share) = sut.composition().unwrap();
+
+            assert_eq!(
+                token_share.0,
+                FixedPointDecimal::try_from(0.666666).unwrap()
+            );
+            assert_eq!(
+                staked_share.0,
+                FixedPointDecimal::try_from(0.333334).unwrap()
+            );
+            assert_eq!((token_share.0 + staked_share.0).unwrap(), 1);
+        }
+
+        #[test]
+        fn errors_on_empty_pool() {
+            let sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            assert_eq!(
+                sut.composition().err().unwrap(),
+                FixedPointError::DivisionByZero
+            );
+        }
+    }
+
+    mod undo {
+        use super::*;
+
+        #[test]
+        fn restores_state_before_last_operation() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                .unwrap();
+            sut.swap(StakedTokenAmount(FixedPointDecimal::try_from(6).unwrap()))
+                .unwrap();
+            sut.remove_liquidity(LpTokenAmount(FixedPointDecimal::try_from(50).unwrap()))
+                .unwrap();
+
+            sut.undo().unwrap();
+            assert_eq!(
+                sut.token_amount.0,
+                FixedPointDecimal::try_from(91.009).unwrap()
+            );
+            assert_eq!(sut.staked_token_amount.0, 6);
+            assert_eq!(sut.lp_token_amount.0, 100);
+
+            sut.undo().unwrap();
+            assert_eq!(sut.token_amount.0, 100);
+            assert_eq!(sut.staked_token_amount.0, 0);
+            assert_eq!(sut.lp_token_amount.0, 100);
+
+            sut.undo().unwrap();
+            assert_eq!(sut.token_amount.0, 0);
+            assert_eq!(sut.lp_token_amount.0, 0);
+        }
+
+        #[test]
+        fn errors_on_fresh_pool() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            assert_eq!(sut.undo().err().unwrap(), FixedPointError::NothingToUndo);
+        }
+
+        #[test]
+        fn failed_operation_is_not_recorded() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            assert!(sut
+                .swap(StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                .is_err());
+            assert_eq!(sut.undo().err().unwrap(), FixedPointError::NothingToUndo);
+        }
+
+        #[test]
+        fn keeps_bounded_history() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            for _ in 0..MAX_UNDO_DEPTH + 4 {
+                sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(1).unwrap()))
+                    .unwrap();
+            }
+            for _ in 0..MAX_UNDO_DEPTH {
+                sut.undo().unwrap();
+            }
+
+            assert_eq!(sut.token_amount.0, 4);
+            assert_eq!(sut.undo().err().unwrap(), FixedPointError::NothingToUndo);
+        }
+
+        #[test]
+        fn reverts_flash_loan_as_a_whole() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+
+            sut.flash_loan(
+                TokenAmount(FixedPointDecimal::try_from(500).unwrap()),
+                |pool| {
+                    pool.swap(StakedTokenAmount(FixedPointDecimal::ONE))?;
+                    Ok(TokenAmount(FixedPointDecimal::try_from(500).unwrap()))
+                },
+            )
+            .unwrap();
+            sut.undo().unwrap();
+
+            assert_eq!(sut.token_amount.0, 1000);
+            assert_eq!(sut.staked_token_amount.0, 0);
+            assert_eq!(sut.lp_token_amount.0, 1000);
+            assert_eq!(sut.undo().err().unwrap(), FixedPointError::NothingToUndo);
+        }
+
+        #[test]
+        fn reverts_single_sided_removal_as_a_whole() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(20).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(330).unwrap()),
+            );
+            let balances_before = sut.balances();
+
+            sut.remove_liquidity_single_sided(LpTokenAmount(
+                FixedPointDecimal::try_from(33).unwrap(),
+            ))
+            .unwrap();
+            sut.undo().unwrap();
+
+            assert_eq!(sut.balances(), balances_before);
+            assert_eq!(sut.undo().err().unwrap(), FixedPointError::NothingToUndo);
+        }
+
+        #[test]
+        fn reverts_lp_cycle_as_a_whole() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(10000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(10000).unwrap()),
+            );
+            let balances_before = sut.balances();
+
+            sut.simulate_lp_cycle(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                &[
+                    StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                    StakedTokenAmount(FixedPointDecimal::try_from(200).unwrap()),
+                ],
+            )
+            .unwrap();
+            sut.undo().unwrap();
+
+            assert_eq!(sut.balances(), balances_before);
+            assert_eq!(sut.undo().err().unwrap(), FixedPointError::NothingToUndo);
+        }
+
+        #[test]
+        fn restores_ticket_and_escrow_after_claim() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let ticket = sut
+                .request_removal(LpTokenAmount(FixedPointDecimal::try_from(40).unwrap()))
+                .unwrap();
+            let ticket_id = ticket.id;
+            let fingerprint_before = sut.state_fingerprint();
+
+            sut.claim_removal(ticket).unwrap();
+            sut.undo().unwrap();
+
+            assert_eq!(sut.state_fingerprint(), fingerprint_before);
+            assert_eq!(sut.escrowed_lp_token_amount.0, 40);
+            assert_eq!(
+                sut.request_removal(LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                    .err(),
+                Some(FixedPointError::Underflow)
+            );
+            assert!(sut.claim_removal(RemovalTicket { id: ticket_id }).is_ok());
+        }
+
+        #[test]
+        fn reverts_removal_request() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let fingerprint_before = sut.state_fingerprint();
+
+            sut.request_removal(LpTokenAmount(FixedPointDecimal::try_from(40).unwrap()))
+                .unwrap();
+            sut.undo().unwrap();
+
+            assert_eq!(sut.state_fingerprint(), fingerprint_before);
+            assert!(sut.pending_removals.is_empty());
+        }
+    }
+
+    mod rebalance_swap_to {
+        use super::*;
+
+        #[test]
+        fn swaps_staked_in_for_over_token_pool() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(450).unwrap()),
+            );
+            let direction = sut
+                .rebalance_swap_to(Percentage(FixedPointDecimal::try_from(0.6).unwrap()))
+                .unwrap();
+
+            assert_eq!(
+                direction,
+                SwapDirection::StakedIn(StakedTokenAmount(
+                    FixedPointDecimal::try_from(20).unwrap()
+                ))
+            );
+        }
+
+        #[test]
+        fn swaps_token_in_for_over_staked_pool() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(200).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(450).unwrap()),
+            );
+            let direction = sut
+                .rebalance_swap_to(Percentage(FixedPointDecimal::try_from(0.6).unwrap()))
+                .unwrap();
+
+            assert_eq!(
+                direction,
+                SwapDirection::TokenIn(TokenAmount(FixedPointDecimal::try_from(120).unwrap()))
+            );
+        }
+
+        #[test]
+        fn is_noop_at_target() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+            let direction = sut
+                .rebalance_swap_to(Percentage(FixedPointDecimal::try_from(0.50005).unwrap()))
+                .unwrap();
+
+            assert_eq!(direction, SwapDirection::None);
+        }
+
+        #[test]
+        fn rejects_share_above_one() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+            let result =
+                sut.rebalance_swap_to(Percentage(FixedPointDecimal::try_from(1.5).unwrap()));
+
+            assert_eq!(result.err().unwrap(), FixedPointError::Underflow);
+        }
+    }
+
+    mod route_swap {
+        use super::*;
+
+        #[test]
+        fn routes_through_both_pools() {
+            let mut pool_a = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let mut pool_b = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let output = route_swap(
+                &mut pool_a,
+                &mut pool_b,
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+            )
+            .unwrap();
+
+            assert_eq!(output.0, FixedPointDecimal::try_from(22.455023).unwrap());
+            assert_eq!(
+                pool_a.token_amount.0,
+                FixedPointDecimal::try_from(985.015).unwrap()
+            );
+            assert_eq!(
+                pool_b.staked_token_amount.0,
+                FixedPointDecimal::try_from(14.985).unwrap()
+            );
+        }
+
+        #[test]
+        fn rolls_back_both_pools_on_failure() {
+            let mut pool_a = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let mut pool_b = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let result = route_swap(
+                &mut pool_a,
+                &mut pool_b,
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+            );
+
+            assert_eq!(result.err().unwrap(), FixedPointError::Underflow);
+            assert_eq!(pool_a.token_amount.0, 1000);
+            assert_eq!(pool_a.staked_token_amount.0, 0);
+            assert_eq!(pool_b.token_amount.0, 10);
+            assert_eq!(pool_b.staked_token_amount.0, 0);
+        }
+    }
+
+    mod effective_rate_with_cost {
+        use super::*;
+
+        #[test]
+        fn cost_matters_more_for_small_trades() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let break_even = FixedPointDecimal::try_from(1.4).unwrap();
+            let cost = TokenAmount(FixedPointDecimal::try_from(0.5).unwrap());
+
+            let large_rate = sut
+                .effective_rate_with_cost(
+                    StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                    TokenAmount::default(),
+                )
+                .unwrap();
+            let large_rate_with_cost = sut
+                .effective_rate_with_cost(
+                    StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                    cost.clone(),
+                )
+                .unwrap();
+            assert_eq!(large_rate.0, FixedPointDecimal::try_from(1.4985).unwrap());
+            assert_eq!(
+                large_rate_with_cost.0,
+                FixedPointDecimal::try_from(1.4935).unwrap()
+            );
+            assert!(large_rate_with_cost.0 > break_even);
+
+            let small_rate = sut
+                .effective_rate_with_cost(
+                    StakedTokenAmount(FixedPointDecimal::try_from(1).unwrap()),
+                    TokenAmount::default(),
+                )
+                .unwrap();
+            let small_rate_with_cost = sut
+                .effective_rate_with_cost(
+                    StakedTokenAmount(FixedPointDecimal::try_from(1).unwrap()),
+                    cost,
+                )
+                .unwrap();
+            assert!(small_rate.0 > break_even);
+            assert_eq!(
+                small_rate_with_cost.0,
+                FixedPointDecimal::try_from(0.9985).unwrap()
+            );
+            assert!(small_rate_with_cost.0 < break_even);
+        }
+
+        #[test]
+        fn errors_when_cost_exceeds_output() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let result = sut.effective_rate_with_cost(
+                StakedTokenAmount(FixedPointDecimal::try_from(1).unwrap()),
+                TokenAmount(FixedPointDecimal::try_from(2).unwrap()),
+            );
+
+            assert_eq!(result.err().unwrap(), FixedPointError::Underflow);
+        }
+    }
+
+    mod marginal_fee_rate {
+        use super::*;
+
+        #[test]
+        fn is_zero_in_flat_region() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let rate = sut
+                .marginal_fee_rate(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            assert_eq!(rate.0, 0);
+        }
+
+        #[test]
+        fn matches_curve_slope_in_sloped_region() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let rate = sut
+                .marginal_fee_rate(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            // (max_fee - min_fee) * price / liquidity_target = 0.089 * 1.5 / 90
+            assert_eq!(rate.0, FixedPointDecimal::try_from(0.001483).unwrap());
+        }
+    }
+
+    mod simulate_lp_cycle {
+        use super::*;
+
+        #[test]
+        fn fees_make_cycle_net_positive() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(10000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(10000).unwrap()),
+            );
+            let deposit = TokenAmount(FixedPointDecimal::try_from(1000).unwrap());
+            let withdrawn = sut
+                .simulate_lp_cycle(
+                    TokenAmount(deposit.0),
+                    &[StakedTokenAmount(
+                        FixedPointDecimal::try_from(7300).unwrap(),
+                    )],
+                )
+                .unwrap();
+
+            assert!(withdrawn.0 > deposit.0);
+            assert_eq!(sut.lp_token_amount.0, 10000);
+        }
+
+        #[test]
+        fn rolls_back_on_failure() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let result = sut.simulate_lp_cycle(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                &[StakedTokenAmount(
+                    FixedPointDecimal::try_from(1000).unwrap(),
+                )],
+            );
+
+            assert_eq!(result.err().unwrap(), FixedPointError::Underflow);
+            assert_eq!(sut.token_amount.0, 100);
+            assert_eq!(sut.lp_token_amount.0, 100);
+            assert_eq!(sut.staked_token_amount.0, 0);
+        }
+    }
+
+    mod is_solvent {
+        use super::*;
+
+        #[test]
+        fn reserve_covering_staked_value_is_solvent() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+            assert!(sut.is_solvent().unwrap());
+        }
+
+        #[test]
+        fn reserve_below_staked_value_is_insolvent() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(149.999999).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+            assert!(!sut.is_solvent().unwrap());
+        }
+    }
+
+    mod arb_break_even_price {
+        use super::*;
+
+        #[test]
+        fn reflects_min_fee() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let price = sut
+                .arb_break_even_price(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            assert_eq!(price.0, FixedPointDecimal::try_from(1.4985).unwrap());
+        }
+
+        #[test]
+        fn moves_down_with_higher_fee() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let price = sut
+                .arb_break_even_price(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            assert_eq!(price.0, FixedPointDecimal::try_from(1.491082).unwrap());
+        }
+    }
+
+    mod protocol_fee {
+        use super::*;
+
+        #[test]
+        fn reduces_trader_output_and_grows_treasury() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.set_protocol_fee(Percentage(FixedPointDecimal::try_from(0.002).unwrap()))
+                .unwrap();
+            let tokens = sut
+                .swap(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            assert_eq!(tokens.0, FixedPointDecimal::try_from(14.955).unwrap());
+            assert_eq!(
+                sut.protocol_treasury().0,
+                FixedPointDecimal::try_from(0.03).unwrap()
+            );
+            assert_eq!(
+                sut.token_amount.0,
+                FixedPointDecimal::try_from(985.015).unwrap()
+            );
+        }
+
+        #[test]
+        fn collecting_drains_treasury() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.set_protocol_fee(Percentage(FixedPointDecimal::try_from(0.002).unwrap()))
+                .unwrap();
+            sut.swap(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            let collected = sut.collect_protocol_fees();
+
+            assert_eq!(collected.0, FixedPointDecimal::try_from(0.03).unwrap());
+            assert_eq!(sut.protocol_treasury().0, 0);
+        }
+
+        #[test]
+        fn is_zero_by_default() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.swap(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            assert_eq!(sut.protocol_treasury().0, 0);
+        }
+    }
+
+    mod fee_earnings_for_share {
+        use super::*;
+
+        #[test]
+        fn scales_linearly_with_share() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let swaps = [
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(20).unwrap()),
+            ];
+
+            let small_share = sut
+                .fee_earnings_for_share(
+                    LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                    &swaps,
+                )
+                .unwrap();
+            let large_share = sut
+                .fee_earnings_for_share(
+                    LpTokenAmount(FixedPointDecimal::try_from(200).unwrap()),
+                    &swaps,
+                )
+                .unwrap();
+            let full_share = sut
+                .fee_earnings_for_share(
+                    LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                    &swaps,
+                )
+                .unwrap();
+
+            assert_eq!(full_share.0, FixedPointDecimal::try_from(0.045).unwrap());
+            assert_eq!(small_share.0, FixedPointDecimal::try_from(0.0045).unwrap());
+            assert_eq!(large_share.0, (small_share.0 * 2).unwrap());
+            assert_eq!(sut.token_amount.0, 1000);
+        }
+    }
+
+    mod deposit_to_reach_target {
+        use super::*;
+
+        #[test]
+        fn returns_shortfall_for_under_target_pool() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(60).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(60).unwrap()),
+            );
+
+            let deposit = sut.deposit_to_reach_target().unwrap();
+            assert_eq!(deposit.0, FixedPointDecimal::try_from(30).unwrap());
+
+            sut.add_liquidity(deposit).unwrap();
+            assert_eq!(sut.token_amount.0, sut.liquidity_target.0);
+        }
+
+        #[test]
+        fn returns_zero_at_or_above_target() {
+            for token_amount in [90, 120] {
+                let sut = create_sut(
+                    TokenAmount(FixedPointDecimal::try_from(token_amount).unwrap()),
+                    StakedTokenAmount::default(),
+                    LpTokenAmount(FixedPointDecimal::try_from(token_amount).unwrap()),
+                );
+
+                assert_eq!(
+                    sut.deposit_to_reach_target().unwrap(),
+                    TokenAmount::default()
+                );
+            }
+        }
+    }
+
+    mod remove_liquidity_rounded {
+        use super::*;
+
+        #[test]
+        fn nearest_rounds_remainder_up_where_floor_truncates() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(200).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(20).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(330).unwrap()),
+            );
+            let lp_token_amount = LpTokenAmount(FixedPointDecimal::try_from(110).unwrap());
+
+            let (floor_tokens, floor_staked_tokens) = sut
+                .clone()
+                .remove_liquidity_rounded(lp_token_amount.clone(), RoundingMode::Floor)
+                .unwrap();
+            let (nearest_tokens, nearest_staked_tokens) = sut
+                .clone()
+                .remove_liquidity_rounded(lp_token_amount, RoundingMode::Nearest)
+                .unwrap();
+
+            // 200 / 3 = 66.666666(6) and 20 / 3 = 6.666666(6) before the 0.1% fee
+            assert_eq!(floor_tokens.0, FixedPointDecimal::try_from(66.6).unwrap());
+            assert_eq!(
+                nearest_tokens.0,
+                FixedPointDecimal::try_from(66.600001).unwrap()
+            );
+            assert_eq!(
+                floor_staked_tokens.0,
+                FixedPointDecimal::try_from(6.66).unwrap()
+            );
+            assert_eq!(
+                nearest_staked_tokens.0,
+                FixedPointDecimal::try_from(6.660001).unwrap()
+            );
+        }
+
+        #[test]
+        fn ceil_never_exceeds_pool_balances() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(200).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(20).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(330).unwrap()),
+            );
+            let (tokens, staked_tokens) = sut
+                .remove_liquidity_rounded(
+                    LpTokenAmount(FixedPointDecimal::try_from(330).unwrap()),
+                    RoundingMode::Ceil,
+                )
+                .unwrap();
+
+            assert!(tokens.0 <= FixedPointDecimal::try_from(200).unwrap());
+            assert!(staked_tokens.0 <= FixedPointDecimal::try_from(20).unwrap());
+            assert_eq!(sut.lp_token_amount.0, FixedPointDecimal::default());
+        }
+    }
+
+    mod quote_swap_at_price {
+        use super::*;
+
+        #[test]
+        fn higher_price_quotes_larger_output() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let staked_token_amount = StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap());
+
+            let current_quote = sut
+                .quote_swap_at_price(staked_token_amount.clone(), sut.price.clone())
+                .unwrap();
+            let higher_quote = sut
+                .quote_swap_at_price(
+                    staked_token_amount,
+                    Price(FixedPointDecimal::try_from(2).unwrap()),
+                )
+                .unwrap();
+
+            assert_eq!(
+                current_quote.0,
+                FixedPointDecimal::try_from(14.985).unwrap()
+            );
+            assert_eq!(higher_quote.0, FixedPointDecimal::try_from(19.98).unwrap());
+            assert!(higher_quote.0 > current_quote.0);
+        }
+
+        #[test]
+        fn leaves_pool_untouched() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            sut.quote_swap_at_price(
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                Price(FixedPointDecimal::try_from(2).unwrap()),
+            )
+            .unwrap();
+
+            assert_eq!(sut.price.0, FixedPointDecimal::try_from(1.5).unwrap());
+            assert_eq!(sut.token_amount.0, 1000);
+        }
+    }
+
+    mod init_with_balances {
+        use super::*;
+
+        fn init_sut(
+            token_amount: TokenAmount,
+            staked_token_amount: StakedTokenAmount,
+            lp_token_amount: LpTokenAmount,
+        ) -> Result<LiquidityPool, FixedPointError> {
+            LiquidityPool::init_with_balances(
+                Price(FixedPointDecimal::try_from(1.5).unwrap()),
+                TokenAmount(FixedPointDecimal::try_from(90).unwrap()),
+                Percentage(FixedPointDecimal::try_from(0.001).unwrap()),
+                Percentage(FixedPointDecimal::try_from(0.09).unwrap()),
+                token_amount,
+                staked_token_amount,
+                lp_token_amount,
+            )
+        }
+
+        #[test]
+        fn accepts_consistent_balances() {
+            let sut = init_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            )
+            .unwrap();
+
+            assert_eq!(sut.token_amount.0, 150);
+            assert_eq!(sut.staked_token_amount.0, 100);
+            assert_eq!(sut.lp_token_amount.0, 300);
+            assert_eq!(sut.tvl().unwrap().0, 300);
+        }
+
+        #[test]
+        fn accepts_empty_pool() {
+            let sut = init_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            )
+            .unwrap();
+
+            assert_eq!(sut.lp_token_amount, LpTokenAmount::default());
+        }
+
+        #[test]
+        fn rejects_balances_without_lp_supply() {
+            let result = init_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+
+            assert_eq!(result.err(), Some(FixedPointError::InvalidInput));
+        }
+
+        #[test]
+        fn rejects_lp_supply_without_balances() {
+            let result = init_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+            );
+
+            assert_eq!(result.err(), Some(FixedPointError::InvalidInput));
+        }
+
+        #[test]
+        fn rejects_insolvent_balances() {
+            let result = init_sut(
+                TokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(160).unwrap()),
+            );
+
+            assert_eq!(result.err(), Some(FixedPointError::InvalidInput));
+        }
+    }
+
+    mod min_out_for_tolerance {
+        use super::*;
+
+        #[test]
+        fn reduces_quote_by_tolerance() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let staked_token_amount = StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap());
+            let quote = sut.clone().swap(staked_token_amount.clone()).unwrap();
+
+            let min_out = sut
+                .min_out_for_tolerance(
+                    staked_token_amount,
+                    Percentage(FixedPointDecimal::try_from(0.01).unwrap()),
+                )
+                .unwrap();
+
+            assert_eq!(quote.0, FixedPointDecimal::try_from(14.985).unwrap());
+            assert_eq!(min_out.0, FixedPointDecimal::try_from(14.83515).unwrap());
+        }
+
+        #[test]
+        fn stays_within_one_ulp_of_exact_result() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let tolerance = Percentage(FixedPointDecimal::try_from(0.333333).unwrap());
+
+            let min_out = sut
+                .min_out_for_tolerance(
+                    StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                    tolerance,
+                )
+                .unwrap();
+
+            // 14.985 * 0.666667 = 9.990004995
+            let exact_nanos = 9_990_004_995_u128;
+            let min_out_nanos = min_out.0.raw() as u128 * 1000;
+            assert!(min_out_nanos <= exact_nanos);
+            assert!(exact_nanos - min_out_nanos < 1000);
+        }
+
+        #[test]
+        fn full_tolerance_allows_zero_output() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let min_out = sut
+                .min_out_for_tolerance(
+                    StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                    Percentage(FixedPointDecimal::try_from(2).unwrap()),
+                )
+                .unwrap();
+
+            assert_eq!(min_out, TokenAmount::default());
+        }
+    }
+
+    mod display_table {
+        use super::*;
+
+        #[test]
+        fn aligns_value_column() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000.25).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(3).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(1004.75).unwrap()),
+            );
+            let table = sut.display_table();
+            let lines: Vec<&str> = table.lines().collect();
+
+            assert_eq!(lines.len(), 7);
+            assert_eq!(lines[0], "Price                       1.5");
+            assert_eq!(lines[4], "Token amount            1000.25");
+            assert!(lines.iter().all(|line| line.len() == lines[0].len()));
+        }
+    }
+
+    mod rounding_drift {
+        use super::*;
+
+        #[test]
+        fn starts_at_zero() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+
+            assert_eq!(sut.rounding_drift(), FixedPointDecimal::default());
+        }
+
+        #[test]
+        fn stays_within_three_units_per_swap() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            sut.set_protocol_fee(Percentage(FixedPointDecimal::try_from(0.0003).unwrap()))
+                .unwrap();
+            let swap_count = 200;
+
+            for _ in 0..swap_count {
+                sut.swap(StakedTokenAmount(
+                    FixedPointDecimal::try_from(0.333333).unwrap(),
+                ))
+                .unwrap();
+            }
+
+            let drift = sut.rounding_drift();
+            assert!(drift > FixedPointDecimal::default());
+            assert!(drift <= FixedPointDecimal::from_raw(3 * swap_count));
+        }
+
+        #[test]
+        fn undo_reverts_drift_of_swap() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+
+            sut.swap(StakedTokenAmount(
+                FixedPointDecimal::try_from(0.333333).unwrap(),
+            ))
+            .unwrap();
+            assert!(sut.rounding_drift() > FixedPointDecimal::default());
+            sut.undo().unwrap();
+
+            assert_eq!(sut.rounding_drift(), FixedPointDecimal::default());
+        }
+    }
+
+    mod simulate_price_shock {
+        use super::*;
+
+        #[test]
+        fn upward_shock_raises_staked_value() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+            let snapshot = sut
+                .simulate_price_shock(
+                    Percentage(FixedPointDecimal::try_from(0.2).unwrap()),
+                    ShockDirection::Up,
+                )
+                .unwrap();
+
+            assert_eq!(snapshot.tvl.0, 330);
+            assert_eq!(
+                snapshot.token_share.0,
+                FixedPointDecimal::try_from(0.454545).unwrap()
+            );
+            assert_eq!(
+                snapshot.staked_share.0,
+                FixedPointDecimal::try_from(0.545455).unwrap()
+            );
+            assert!(!snapshot.is_solvent);
+        }
+
+        #[test]
+        fn downward_shock_lowers_staked_value() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+            let snapshot = sut
+                .simulate_price_shock(
+                    Percentage(FixedPointDecimal::try_from(0.2).unwrap()),
+                    ShockDirection::Down,
+                )
+                .unwrap();
+
+            assert_eq!(snapshot.tvl.0, 270);
+            assert_eq!(
+                snapshot.token_share.0,
+                FixedPointDecimal::try_from(0.555555).unwrap()
+            );
+            assert!(snapshot.is_solvent);
+        }
+
+        #[test]
+        fn leaves_original_pool_unchanged() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+            sut.simulate_price_shock(
+                Percentage(FixedPointDecimal::try_from(0.5).unwrap()),
+                ShockDirection::Up,
+            )
+            .unwrap();
+
+            assert_eq!(sut.price.0, FixedPointDecimal::try_from(1.5).unwrap());
+            assert_eq!(sut.tvl().unwrap().0, 300);
+        }
+    }
+
+    mod add_balanced_liquidity {
+        use super::*;
+
+        #[test]
+        fn keeps_composition_unchanged() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(450).unwrap()),
+            );
+            let composition_before = sut.composition().unwrap();
+
+            let (staked_tokens, lp_tokens) = sut
+                .add_balanced_liquidity(TokenAmount(FixedPointDecimal::try_from(30).unwrap()))
+                .unwrap();
+
+            assert_eq!(staked_tokens.0, 10);
+            assert_eq!(lp_tokens.0, 45);
+            assert_eq!(sut.token_amount.0, 330);
+            assert_eq!(sut.staked_token_amount.0, 110);
+            assert_eq!(sut.lp_token_amount.0, 495);
+
+            let composition_after = sut.composition().unwrap();
+            assert_eq!(composition_after.0 .0, composition_before.0 .0);
+            assert_eq!(composition_after.1 .0, composition_before.1 .0);
+        }
+
+        #[test]
+        fn rounds_required_staked_tokens_up() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(450).unwrap()),
+            );
+
+            let (staked_tokens, _) = sut
+                .add_balanced_liquidity(TokenAmount(FixedPointDecimal::try_from(1).unwrap()))
+                .unwrap();
+
+            assert_eq!(
+                staked_tokens.0,
+                FixedPointDecimal::try_from(0.333334).unwrap()
+            );
+        }
+
+        #[test]
+        fn degrades_to_single_sided_on_empty_pool() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+
+            let (staked_tokens, lp_tokens) = sut
+                .add_balanced_liquidity(TokenAmount(FixedPointDecimal::try_from(50).unwrap()))
+                .unwrap();
+
+            assert_eq!(staked_tokens, StakedTokenAmount::default());
+            assert_eq!(lp_tokens.0, 50);
+            assert_eq!(sut.token_amount.0, 50);
+        }
+    }
+
+    mod set_liquidity_target {
+        use super::*;
+
+        #[test]
+        fn raising_target_increases_fee_on_next_swap() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let staked_token_amount = StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap());
+            let tokens_at_old_target = sut.clone().swap(staked_token_amount.clone()).unwrap();
+
+            sut.set_liquidity_target(TokenAmount(FixedPointDecimal::try_from(200).unwrap()))
+                .unwrap();
+            let tokens_at_new_target = sut.swap(staked_token_amount).unwrap();
+
+            // fee = 0.09 - 0.089 * 85 / target
+            assert_eq!(
+                tokens_at_old_target.0,
+                FixedPointDecimal::try_from(14.910825).unwrap()
+            );
+            assert_eq!(
+                tokens_at_new_target.0,
+                FixedPointDecimal::try_from(14.217375).unwrap()
+            );
+        }
+
+        #[test]
+        fn rejects_zero_target() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+
+            assert_eq!(
+                sut.set_liquidity_target(TokenAmount::default()).err(),
+                Some(FixedPointError::DivisionByZero)
+            );
+            assert_eq!(sut.liquidity_target.0, 90);
+        }
+    }
+
+    mod lp_token_backing {
+        use super::*;
+
+        #[test]
+        fn splits_reserves_per_lp_token() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(400).unwrap()),
+            );
+            let (tokens, staked_tokens) = sut.lp_token_backing().unwrap();
+
+            assert_eq!(tokens.0, FixedPointDecimal::try_from(0.75).unwrap());
+            assert_eq!(staked_tokens.0, FixedPointDecimal::try_from(0.25).unwrap());
+        }
+
+        #[test]
+        fn scaled_backing_matches_pre_fee_removal() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(400).unwrap()),
+            );
+            let holder_lp = FixedPointDecimal::try_from(40).unwrap();
+            let (tokens, staked_tokens) = sut.lp_token_backing().unwrap();
+            let backed_tokens = (tokens.0 * holder_lp).unwrap();
+            let backed_staked_tokens = (staked_tokens.0 * holder_lp).unwrap();
+
+            let (_, _, fee) = sut
+                .quote_remove_liquidity(LpTokenAmount(holder_lp))
+                .unwrap();
+            let (removed_tokens, removed_staked_tokens) =
+                sut.remove_liquidity(LpTokenAmount(holder_lp)).unwrap();
+
+            assert_eq!(
+                removed_tokens.0,
+                sut.apply_fee(backed_tokens, &fee).unwrap()
+            );
+            assert_eq!(
+                removed_staked_tokens.0,
+                sut.apply_fee(backed_staked_tokens, &fee).unwrap()
+            );
+        }
+
+        #[test]
+        fn errors_on_empty_pool() {
+            let sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+
+            assert_eq!(
+                sut.lp_token_backing().err(),
+                Some(FixedPointError::DivisionByZero)
+            );
+        }
+    }
+
+    mod remove_liquidity_single_sided {
+        use super::*;
+
+        #[test]
+        fn matches_removing_then_swapping() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(20).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(330).unwrap()),
+            );
+            let lp_token_amount = LpTokenAmount(FixedPointDecimal::try_from(33).unwrap());
+
+            let mut manual_pool = sut.clone();
+            let (tokens, staked_tokens) = manual_pool
+                .remove_liquidity(lp_token_amount.clone())
+                .unwrap();
+            let swapped_tokens = manual_pool.swap(staked_tokens).unwrap();
+            let expected_tokens = (tokens.0 + swapped_tokens.0).unwrap();
+
+            let single_sided_tokens = sut.remove_liquidity_single_sided(lp_token_amount).unwrap();
+
+            assert_eq!(single_sided_tokens.0, expected_tokens);
+            assert_eq!(
+                single_sided_tokens.0,
+                FixedPointDecimal::try_from(32.964003).unwrap()
+            );
+            assert_eq!(sut.token_amount.0, manual_pool.token_amount.0);
+            assert_eq!(sut.staked_token_amount.0, 20);
+        }
+
+        #[test]
+        fn restores_pool_when_swap_cannot_be_covered() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(160).unwrap()),
+            );
+
+            let result = sut.remove_liquidity_single_sided(LpTokenAmount(
+                FixedPointDecimal::try_from(80).unwrap(),
+            ));
+
+            assert_eq!(result.err(), Some(FixedPointError::Underflow));
+            assert_eq!(sut.token_amount.0, 10);
+            assert_eq!(sut.staked_token_amount.0, 100);
+            assert_eq!(sut.lp_token_amount.0, 160);
+        }
+    }
+
+    mod is_empty {
+        use super::*;
+
+        #[test]
+        fn fresh_pool_is_empty() {
+            let sut = LiquidityPool::init(
+                Price(FixedPointDecimal::try_from(1.5).unwrap()),
+                TokenAmount(FixedPointDecimal::try_from(90).unwrap()),
+                Percentage(FixedPointDecimal::try_from(0.001).unwrap()),
+                Percentage(FixedPointDecimal::try_from(0.09).unwrap()),
+            )
+            .unwrap();
+
+            assert!(sut.is_empty());
+        }
+
+        #[test]
+        fn pool_with_liquidity_is_not_empty() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                .unwrap();
+
+            assert!(!sut.is_empty());
+        }
+
+        #[test]
+        fn drained_pool_is_empty_again() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let lp_tokens = sut
+                .add_liquidity(TokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                .unwrap();
+            sut.remove_liquidity(lp_tokens).unwrap();
+
+            assert!(sut.is_empty());
+        }
+    }
+
+    mod lp_mint_rate {
+        use super::*;
+
+        #[test]
+        fn is_one_for_empty_pool() {
+            let sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+
+            assert_eq!(sut.lp_mint_rate().unwrap(), FixedPointDecimal::ONE);
+        }
+
+        #[test]
+        fn predicts_minted_lp_tokens() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(600).unwrap()),
+            );
+            let deposit = FixedPointDecimal::try_from(10).unwrap();
+            let rate = sut.lp_mint_rate().unwrap();
+
+            let lp_tokens = sut.add_liquidity(TokenAmount(deposit)).unwrap();
+
+            assert_eq!(rate, 2u64);
+            assert_eq!(lp_tokens.0, (deposit * rate).unwrap());
+        }
+    }
+
+    mod solvency_price_limit {
+        use super::*;
+
+        #[test]
+        fn is_reserve_ratio_with_staked_tokens() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(60).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(240).unwrap()),
+            );
+            let limit = sut.solvency_price_limit().unwrap();
+
+            assert_eq!(limit.0, FixedPointDecimal::try_from(2.5).unwrap());
+
+            let at_limit = LiquidityPool {
+                price: limit.clone(),
+                ..sut.clone()
+            };
+            let above_limit = LiquidityPool {
+                price: Price((limit.0 + FixedPointDecimal::from_raw(1)).unwrap()),
+                ..sut
+            };
+            assert!(at_limit.is_solvent().unwrap());
+            assert!(!above_limit.is_solvent().unwrap());
+        }
+
+        #[test]
+        fn is_max_without_staked_tokens() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+            );
+
+            assert_eq!(
+                sut.solvency_price_limit().unwrap().0,
+                FixedPointDecimal::MAX
+            );
+        }
+    }
+
+    #[cfg(feature = "instrument")]
+    mod last_op_cost {
+        use super::*;
+
+        #[test]
+        fn counts_swap_operations() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            sut.swap(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            // value_of, the LP fee and the protocol fee; min fee needs no interpolation
+            assert_eq!(
+                sut.last_op_cost(),
+                OpCost {
+                    multiplications: 3,
+                    divisions: 0,
+                }
+            );
+        }
+
+        #[test]
+        fn counts_fee_interpolation_below_target() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            sut.swap(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            assert_eq!(
+                sut.last_op_cost(),
+                OpCost {
+                    multiplications: 4,
+                    divisions: 1,
+                }
+            );
+        }
+    }
+
+    mod fee_curve_samples {
+        use super::*;
+
+        #[test]
+        fn spans_max_fee_to_min_fee_monotonically() {
+            let sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let samples = sut.fee_curve_samples(7).unwrap();
+
+            assert_eq!(samples.len(), 7);
+            assert_eq!(samples[0].0, TokenAmount::default());
+            assert_eq!(samples[0].1 .0, sut.max_fee.0);
+            assert_eq!(samples[4].0 .0, sut.liquidity_target.0);
+            assert_eq!(samples[4].1 .0, sut.min_fee.0);
+            assert_eq!(samples[6].0 .0, 135);
+            assert!(samples
+                .windows(2)
+                .all(|pair| pair[0].0 .0 < pair[1].0 .0 && pair[0].1 .0 >= pair[1].1 .0));
+        }
+
+        #[test]
+        fn handles_degenerate_sample_counts() {
+            let sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+
+            assert!(sut.fee_curve_samples(0).unwrap().is_empty());
+            assert_eq!(sut.fee_curve_samples(1).unwrap().len(), 1);
+        }
+    }
+
+    mod permille_fees {
+        use super::*;
+
+        #[test]
+        fn match_float_constructed_fees() {
+            let float_pool = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let permille_pool = LiquidityPool {
+                min_fee: Percentage::from_permille(1).unwrap(),
+                max_fee: Percentage::from_permille(90).unwrap(),
+                ..float_pool.clone()
+            };
+
+            for final_liquidity in [0, 45, 85, 90, 120] {
+                let final_liquidity =
+                    TokenAmount(FixedPointDecimal::try_from(final_liquidity).unwrap());
+                assert_eq!(
+                    permille_pool
+                        .calculate_fee(final_liquidity.clone())
+                        .unwrap()
+                        .0,
+                    float_pool.calculate_fee(final_liquidity).unwrap().0
+                );
+            }
+        }
+    }
+
+    mod min_profitable_swap {
+        use super::*;
+
+        #[test]
+        fn threshold_yields_output_and_one_unit_less_does_not() {
+            let mut sut = LiquidityPool {
+                price: Price(FixedPointDecimal::try_from(0.3).unwrap()),
+                ..create_sut(
+                    TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                    StakedTokenAmount::default(),
+                    LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                )
+            };
+            let threshold = sut.min_profitable_swap().unwrap();
+
+            assert_eq!(threshold.0, FixedPointDecimal::from_raw(4));
+            assert_eq!(
+                sut.clone()
+                    .swap(StakedTokenAmount(FixedPointDecimal::from_raw(3)))
+                    .unwrap(),
+                TokenAmount::default()
+            );
+            assert_eq!(
+                sut.swap(threshold).unwrap().0,
+                FixedPointDecimal::from_raw(1)
+            );
+        }
+
+        #[test]
+        fn errors_when_fee_consumes_everything() {
+            let sut = LiquidityPool {
+                min_fee: Percentage(FixedPointDecimal::ONE),
+                ..create_sut(
+                    TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                    StakedTokenAmount::default(),
+                    LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                )
+            };
+
+            assert_eq!(
+                sut.min_profitable_swap().err(),
+                Some(FixedPointError::InvalidInput)
+            );
+        }
+    }
+
+    mod implied_fee {
+        use super::*;
+
+        #[test]
+        fn recovers_min_fee_from_swap() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let staked_token_amount = StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap());
+            let gross_out = sut.price.value_of(&staked_token_amount).unwrap();
+            let net_out = sut.swap(staked_token_amount).unwrap();
+
+            let fee = LiquidityPool::implied_fee(gross_out, net_out).unwrap();
+
+            assert_eq!(fee.0, FixedPointDecimal::try_from(0.001).unwrap());
+        }
+
+        #[test]
+        fn errors_on_zero_gross() {
+            assert_eq!(
+                LiquidityPool::implied_fee(TokenAmount::default(), TokenAmount::default()).err(),
+                Some(FixedPointError::DivisionByZero)
+            );
+        }
+    }
+
+    mod depth_at_impact {
+        use super::*;
+
+        #[test]
+        fn min_fee_impact_reaches_liquidity_target() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let depth = sut
+                .depth_at_impact(Percentage(FixedPointDecimal::try_from(0.001).unwrap()))
+                .unwrap();
+
+            // 10 tokens above the target at a price of 1.5, plus the sliver past it where the
+            // fee increase is still below the impact's sixth decimal
+            assert!(depth.0 >= FixedPointDecimal::try_from(6.666666).unwrap());
+            assert!(depth.0 < FixedPointDecimal::try_from(6.67).unwrap());
+        }
+
+        #[test]
+        fn grows_with_tolerance() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let depths: Vec<FixedPointDecimal> = [0.001, 0.005, 0.01, 0.05]
+                .into_iter()
+                .map(|impact| {
+                    sut.depth_at_impact(Percentage(FixedPointDecimal::try_from(impact).unwrap()))
+                        .unwrap()
+                        .0
+                })
+                .collect();
+
+            assert!(depths.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+
+        #[test]
+        fn is_zero_below_min_fee() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let depth = sut
+                .depth_at_impact(Percentage(FixedPointDecimal::try_from(0.0001).unwrap()))
+                .unwrap();
+
+            assert!(depth.0 < FixedPointDecimal::try_from(0.01).unwrap());
+        }
+    }
+
+    mod balances {
+        use super::*;
+
+        #[test]
+        fn reflect_swap_deltas() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let (tokens_before, staked_tokens_before, lp_tokens_before) = sut.balances();
+
+            let tokens_out = sut
+                .swap(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+            let (tokens_after, staked_tokens_after, lp_tokens_after) = sut.balances();
+
+            assert_eq!((tokens_before.0 - tokens_after.0).unwrap(), tokens_out.0);
+            assert_eq!(
+                (staked_tokens_after.0 - staked_tokens_before.0).unwrap(),
+                10
+            );
+            assert_eq!(lp_tokens_after, lp_tokens_before);
+        }
+    }
+
+    mod validate_fee_config {
+        use super::*;
+
+        fn init_sut(min_fee: f64, max_fee: f64) -> Result<LiquidityPool, FixedPointError> {
+            LiquidityPool::init(
+                Price(FixedPointDecimal::try_from(1.5).unwrap()),
+                TokenAmount(FixedPointDecimal::try_from(90).unwrap()),
+                Percentage(FixedPointDecimal::try_from(min_fee).unwrap()),
+                Percentage(FixedPointDecimal::try_from(max_fee).unwrap()),
+            )
+        }
+
+        #[test]
+        fn accepts_valid_configs() {
+            for (min_fee, max_fee) in [(0.001, 0.09), (0.0, 0.0), (0.05, 0.05), (0.0, 1.0)] {
+                assert!(init_sut(min_fee, max_fee).is_ok());
+            }
+        }
+
+        #[test]
+        fn rejects_min_fee_above_max_fee() {
+            assert_eq!(
+                init_sut(0.09, 0.001).err(),
+                Some(FixedPointError::InvalidPercentage)
+            );
+        }
+
+        #[test]
+        fn rejects_max_fee_above_one() {
+            assert_eq!(
+                init_sut(0.001, 1.000001).err(),
+                Some(FixedPointError::InvalidPercentage)
+            );
+        }
+
+        #[test]
+        fn negative_fee_cannot_be_constructed() {
+            let zero_fee = Percentage(FixedPointDecimal::default());
+            let negative_fee = FixedPointDecimal::default() - FixedPointDecimal::ONE;
+
+            assert_eq!(negative_fee.err(), Some(FixedPointError::Underflow));
+            assert!(LiquidityPool::validate_fee_config(zero_fee.clone(), zero_fee).is_ok());
+        }
+
+        #[test]
+        fn setters_reject_fees_above_one() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let fee_above_one = Percentage(FixedPointDecimal::try_from(1.5).unwrap());
+
+            assert_eq!(
+                sut.set_protocol_fee(fee_above_one.clone()).err(),
+                Some(FixedPointError::InvalidPercentage)
+            );
+            assert_eq!(
+                sut.set_flash_fee(fee_above_one).err(),
+                Some(FixedPointError::InvalidPercentage)
+            );
+            assert_eq!(sut.protocol_fee.0, 0);
+            assert_eq!(sut.flash_fee.0, 0);
+            assert!(sut
+                .set_protocol_fee(Percentage(FixedPointDecimal::ONE))
+                .is_ok());
+        }
+    }
+
+    mod swap_partial {
+        use super::*;
+
+        #[test]
+        fn fills_satisfiable_swap_completely() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let (filled, tokens) = sut
+                .swap_partial(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            assert_eq!(filled.0, 10);
+            assert_eq!(tokens.0, FixedPointDecimal::try_from(14.985).unwrap());
+        }
+
+        #[test]
+        fn caps_oversized_swap_at_reserve() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+            let requested = StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap());
+            let (filled, tokens) = sut.swap_partial(requested.clone()).unwrap();
+
+            assert_eq!(filled.0, FixedPointDecimal::try_from(66.666667).unwrap());
+            assert_eq!(
+                (requested.0 - filled.0).unwrap(),
+                FixedPointDecimal::try_from(33.333333).unwrap()
+            );
+            // The reserve is drained to zero, so the max fee applies
+            assert_eq!(tokens.0, 91);
+            assert_eq!(sut.token_amount.0, 9);
+        }
+    }
+
+    mod quote_add_then_swap {
+        use super::*;
+
+        #[test]
+        fn matches_executing_both_steps() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(80).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(95).unwrap()),
+            );
+            let deposit = TokenAmount(FixedPointDecimal::try_from(40).unwrap());
+            let swap_in = StakedTokenAmount(FixedPointDecimal::try_from(20).unwrap());
+
+            let (quoted_lp_tokens, quoted_tokens) = sut
+                .quote_add_then_swap(deposit.clone(), swap_in.clone())
+                .unwrap();
+            assert_eq!(sut.token_amount.0, 80);
+            assert_eq!(sut.lp_token_amount.0, 95);
+
+            let lp_tokens = sut.add_liquidity(deposit).unwrap();
+            let tokens = sut.swap(swap_in).unwrap();
+
+            assert_eq!(quoted_lp_tokens, lp_tokens);
+            assert_eq!(quoted_tokens, tokens);
+        }
+    }
+
+    mod max_safe_deposit {
+        use super::*;
+
+        #[test]
+        fn reserve_limit_near_max() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::from_raw(u64::MAX - 1000)),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::ONE),
+            );
+            let limit = sut.max_safe_deposit().unwrap();
+            assert_eq!(limit.0.raw(), 1000);
+
+            let one_more = TokenAmount((limit.0 + FixedPointDecimal::from_raw(1)).unwrap());
+            assert_eq!(
+                sut.clone().add_liquidity(one_more).err(),
+                Some(FixedPointError::Overflow)
+            );
+            assert!(sut.add_liquidity(limit).is_ok());
+        }
+
+        #[test]
+        fn lp_supply_limit_near_max() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::from_raw(3_000_000)),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::from_raw(u64::MAX / 2)),
+            );
+            let limit = sut.max_safe_deposit().unwrap();
+
+            let one_more = TokenAmount((limit.0 + FixedPointDecimal::from_raw(1)).unwrap());
+            assert_eq!(
+                sut.clone().add_liquidity(one_more).err(),
+                Some(FixedPointError::Overflow)
+            );
+            sut.add_liquidity(limit).unwrap();
+            assert!(sut.lp_token_amount.0 > FixedPointDecimal::from_raw(u64::MAX - 10));
+        }
+
+        #[test]
+        fn net_principal_limit_near_max() {
+            let mut sut = LiquidityPool {
+                net_principal: TokenAmount(FixedPointDecimal::from_raw(u64::MAX - 1000)),
+                ..create_sut(
+                    TokenAmount(FixedPointDecimal::ONE),
+                    StakedTokenAmount::default(),
+                    LpTokenAmount(FixedPointDecimal::ONE),
+                )
+            };
+            let limit = sut.max_safe_deposit().unwrap();
+            assert_eq!(limit.0.raw(), 1000);
+
+            let one_more = TokenAmount((limit.0 + FixedPointDecimal::from_raw(1)).unwrap());
+            assert_eq!(
+                sut.clone().add_liquidity(one_more).err(),
+                Some(FixedPointError::Overflow)
+            );
+            assert!(sut.add_liquidity(limit).is_ok());
+        }
+    }
+
+    mod determinism {
+        use super::*;
+
+        #[test]
+        fn replay_from_raw_values_is_bit_exact() {
+            let mut sut = LiquidityPool::init(
+                Price(FixedPointDecimal::from_raw(1_500_000)),
+                TokenAmount(FixedPointDecimal::from_raw(90_000_000)),
+                Percentage(FixedPointDecimal::from_raw(1_000)),
+                Percentage(FixedPointDecimal::from_raw(90_000)),
+            )
+            .unwrap();
+
+            // Expected values were computed independently with integer arithmetic mirroring the
+            // u128 formulas: value = staked * price / 10^6, fee = max - (max - min) * final /
+            // target below the target, and every division truncating.
+            let lp_tokens = sut
+                .add_liquidity(TokenAmount(FixedPointDecimal::from_raw(100_000_000)))
+                .unwrap();
+            assert_eq!(lp_tokens.0.raw(), 100_000_000);
+
+            let tokens = sut
+                .swap(StakedTokenAmount(FixedPointDecimal::from_raw(6_000_000)))
+                .unwrap();
+            assert_eq!(tokens.0.raw(), 8_991_000);
+
+            let lp_tokens = sut
+                .add_liquidity(TokenAmount(FixedPointDecimal::from_raw(10_000_000)))
+                .unwrap();
+            assert_eq!(lp_tokens.0.raw(), 9_999_100);
+
+            let tokens = sut
+                .swap(StakedTokenAmount(FixedPointDecimal::from_raw(10_000_000)))
+                .unwrap();
+            assert_eq!(tokens.0.raw(), 14_925_795);
+
+            let (tokens, staked_tokens) = sut
+                .remove_liquidity(LpTokenAmount(FixedPointDecimal::from_raw(50_000_000)))
+                .unwrap();
+            assert_eq!(tokens.0.raw(), 37_424_261);
+            assert_eq!(staked_tokens.0.raw(), 6_955_924);
+
+            let tokens = sut
+                .swap(StakedTokenAmount(FixedPointDecimal::from_raw(2_500_000)))
+                .unwrap();
+            assert_eq!(tokens.0.raw(), 3_579_034);
+
+            assert_eq!(sut.token_amount.0.raw(), 45_079_910);
+            assert_eq!(sut.staked_token_amount.0.raw(), 11_544_076);
+            assert_eq!(sut.lp_token_amount.0.raw(), 59_999_100);
+        }
+    }
+
+    mod donate {
+        use super::*;
+
+        #[test]
+        fn raises_lp_token_price_without_minting() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+            let price_before = sut.lp_token_price().unwrap();
+
+            sut.donate(TokenAmount(FixedPointDecimal::try_from(30).unwrap()))
+                .unwrap();
+
+            assert_eq!(price_before.0, FixedPointDecimal::ONE);
             assert_eq!(
-                sut.lp_token_amount.0,
-                FixedPointDecimal::try_from(109.9991).unwrap()
+                sut.lp_token_price().unwrap().0,
+                FixedPointDecimal::try_from(1.1).unwrap()
+            );
+            assert_eq!(sut.lp_token_amount.0, 300);
+            assert_eq!(sut.token_amount.0, 180);
+        }
+
+        #[test]
+        fn can_be_undone() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(150).unwrap()),
             );
+            sut.donate(TokenAmount(FixedPointDecimal::try_from(30).unwrap()))
+                .unwrap();
+            sut.undo().unwrap();
+
+            assert_eq!(sut.token_amount.0, 150);
         }
     }
 
-    mod swap {
+    mod swaps_to_breakeven {
         use super::*;
 
         #[test]
-        fn should_swap_with_min_fee() {
+        fn counts_swaps_rounding_up() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+
+            // 1% of a 1000 TVL against a 0.015 fee per swap: 666.67 swaps
+            let swaps = sut
+                .swaps_to_breakeven(
+                    Percentage(FixedPointDecimal::try_from(0.01).unwrap()),
+                    StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                )
+                .unwrap();
+
+            assert_eq!(swaps, 667);
+        }
+
+        #[test]
+        fn errors_when_swap_earns_no_fee() {
+            let sut = LiquidityPool {
+                min_fee: Percentage::default(),
+                ..create_sut(
+                    TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                    StakedTokenAmount::default(),
+                    LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                )
+            };
+
+            assert_eq!(
+                sut.swaps_to_breakeven(
+                    Percentage(FixedPointDecimal::try_from(0.01).unwrap()),
+                    StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                )
+                .err(),
+                Some(FixedPointError::DivisionByZero)
+            );
+        }
+    }
+
+    mod best_venue {
+        use super::*;
+
+        #[test]
+        fn picks_higher_price() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let pools = [
+                sut.clone(),
+                LiquidityPool {
+                    price: Price(FixedPointDecimal::try_from(1.6).unwrap()),
+                    ..sut
+                },
+            ];
+            let (index, tokens) = best_venue(
+                &pools,
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+            )
+            .unwrap();
+
+            assert_eq!(index, 1);
+            assert_eq!(tokens.0, FixedPointDecimal::try_from(15.984).unwrap());
+        }
+
+        #[test]
+        fn picks_lower_fee() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let pools = [
+                LiquidityPool {
+                    min_fee: Percentage(FixedPointDecimal::try_from(0.01).unwrap()),
+                    ..sut.clone()
+                },
+                sut,
+            ];
+            let (index, _) = best_venue(
+                &pools,
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+            )
+            .unwrap();
+
+            assert_eq!(index, 1);
+        }
+
+        #[test]
+        fn skips_pools_that_cannot_fill() {
+            let pools = [
+                LiquidityPool {
+                    price: Price(FixedPointDecimal::try_from(2.0).unwrap()),
+                    ..create_sut(
+                        TokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                        StakedTokenAmount::default(),
+                        LpTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                    )
+                },
+                create_sut(
+                    TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                    StakedTokenAmount::default(),
+                    LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                ),
+            ];
+            let (index, _) = best_venue(
+                &pools,
+                StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+            )
+            .unwrap();
+
+            assert_eq!(index, 1);
+            assert_eq!(
+                best_venue(
+                    &pools[..1],
+                    StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()),
+                )
+                .err(),
+                Some(FixedPointError::Underflow)
+            );
+        }
+    }
+
+    mod exact_out_with_fee {
+        use super::*;
+
+        fn assert_swap_pays_out(mut sut: LiquidityPool, token_out: FixedPointDecimal) {
+            let staked_token_amount = sut.exact_out_with_fee(TokenAmount(token_out)).unwrap();
+            let tokens = sut.swap(staked_token_amount).unwrap();
+
+            assert!(tokens.0 >= token_out);
+            assert!((tokens.0 - token_out).unwrap() <= FixedPointDecimal::from_raw(1));
+        }
+
+        #[test]
+        fn inverts_swap_in_min_fee_region() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+
+            assert_eq!(
+                sut.exact_out_with_fee(TokenAmount(FixedPointDecimal::try_from(14.985).unwrap()))
+                    .unwrap()
+                    .0,
+                10
+            );
+            assert_swap_pays_out(sut, FixedPointDecimal::try_from(123.456789).unwrap());
+        }
+
+        #[test]
+        fn inverts_swap_in_sloped_fee_region() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+
+            assert_swap_pays_out(sut.clone(), FixedPointDecimal::try_from(14.910825).unwrap());
+            assert_swap_pays_out(sut, FixedPointDecimal::try_from(55.5).unwrap());
+        }
+
+        #[test]
+        fn rejects_unreachable_output() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+            );
+
+            assert_eq!(
+                sut.exact_out_with_fee(TokenAmount(FixedPointDecimal::try_from(95).unwrap()))
+                    .err(),
+                Some(FixedPointError::Underflow)
+            );
+        }
+    }
+
+    mod max_absolute_fee {
+        use super::*;
+
+        #[test]
+        fn caps_fee_on_large_swap() {
             let mut sut = create_sut(
                 TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
                 StakedTokenAmount::default(),
-                LpTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            let staked_token_amount = StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap());
+            let uncapped_tokens = sut.clone().swap(staked_token_amount.clone()).unwrap();
+
+            sut.set_max_absolute_fee(Some(TokenAmount(
+                FixedPointDecimal::try_from(0.01).unwrap(),
+            )));
+            let capped_tokens = sut.swap(staked_token_amount).unwrap();
+
+            assert_eq!(
+                uncapped_tokens.0,
+                FixedPointDecimal::try_from(149.85).unwrap()
+            );
+            assert_eq!(
+                capped_tokens.0,
+                FixedPointDecimal::try_from(149.99).unwrap()
+            );
+            assert_eq!(
+                sut.token_amount.0,
+                FixedPointDecimal::try_from(850.01).unwrap()
             );
+            assert_eq!(sut.rounding_drift(), FixedPointDecimal::default());
+        }
+
+        #[test]
+        fn leaves_small_fees_untouched() {
+            let mut sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+            sut.set_max_absolute_fee(Some(TokenAmount(FixedPointDecimal::try_from(1).unwrap())));
+
             let tokens = sut
                 .swap(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
                 .unwrap();
 
             assert_eq!(tokens.0, FixedPointDecimal::try_from(14.985).unwrap());
+        }
+    }
+
+    mod lp_price_delta_of_swap {
+        use super::*;
+
+        #[test]
+        fn grows_with_swap_size() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+                StakedTokenAmount::default(),
+                LpTokenAmount(FixedPointDecimal::try_from(1000).unwrap()),
+            );
+
+            let small_delta = sut
+                .lp_price_delta_of_swap(StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+            let large_delta = sut
+                .lp_price_delta_of_swap(StakedTokenAmount(FixedPointDecimal::try_from(20).unwrap()))
+                .unwrap();
+
+            assert_eq!(small_delta, FixedPointDecimal::try_from(0.000015).unwrap());
+            assert_eq!(large_delta, (small_delta * 2).unwrap());
+            assert_eq!(sut.lp_token_price().unwrap().0, FixedPointDecimal::ONE);
+        }
+    }
+
+    mod state_fingerprint {
+        use super::*;
+
+        #[test]
+        fn matches_for_identical_pools() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+            let other = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+
+            assert_eq!(sut.state_fingerprint(), other.state_fingerprint());
+        }
+
+        #[test]
+        fn differs_by_one_raw_unit() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+            let mut other = sut.clone();
+            other.token_amount.0 = (other.token_amount.0 + FixedPointDecimal::from_raw(1)).unwrap();
+
+            assert_ne!(sut.state_fingerprint(), other.state_fingerprint());
+        }
+
+        #[test]
+        fn distinguishes_swapped_fields() {
+            let sut = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+            let other = create_sut(
+                TokenAmount(FixedPointDecimal::try_from(150).unwrap()),
+                StakedTokenAmount(FixedPointDecimal::try_from(100).unwrap()),
+                LpTokenAmount(FixedPointDecimal::try_from(300).unwrap()),
+            );
+
+            assert_ne!(sut.state_fingerprint(), other.state_fingerprint());
+        }
+    }
+
+    mod minimum_first_deposit {
+        use super::*;
+
+        #[test]
+        fn rejects_undersized_first_deposit() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.set_minimum_first_deposit(TokenAmount(FixedPointDecimal::try_from(10).unwrap()));
+            let result =
+                sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(9.999999).unwrap()));
+
+            assert_eq!(result.err(), Some(FixedPointError::DepositTooSmall));
+            assert!(sut.is_empty());
+        }
+
+        #[test]
+        fn accepts_sufficient_first_deposit() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.set_minimum_first_deposit(TokenAmount(FixedPointDecimal::try_from(10).unwrap()));
+            let lp_tokens = sut
+                .add_liquidity(TokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            assert_eq!(lp_tokens.0, 10);
+        }
+
+        #[test]
+        fn does_not_apply_once_pool_is_funded() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.set_minimum_first_deposit(TokenAmount(FixedPointDecimal::try_from(10).unwrap()));
+            sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+
+            let lp_tokens = sut
+                .add_liquidity(TokenAmount(FixedPointDecimal::try_from(0.5).unwrap()))
+                .unwrap();
+
+            assert_eq!(lp_tokens.0, FixedPointDecimal::try_from(0.5).unwrap());
+        }
+    }
+
+    mod tokens_to_staked {
+        use super::*;
+
+        #[test]
+        fn round_trips_through_price() {
+            let sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            let token_amount = TokenAmount(FixedPointDecimal::try_from(15).unwrap());
+
+            let staked_tokens = sut.tokens_to_staked(token_amount.clone()).unwrap();
+
+            assert_eq!(staked_tokens.0, 10);
             assert_eq!(
-                sut.token_amount.0,
-                FixedPointDecimal::try_from(985.015).unwrap()
+                sut.calculate_staked_token_value(&staked_tokens).unwrap(),
+                token_amount
             );
-            assert_eq!(sut.staked_token_amount.0, 10);
         }
 
         #[test]
-        fn should_swap_with_max_fee() {
-            assert!(true);
+        fn errors_on_zero_price() {
+            let sut = LiquidityPool {
+                price: Price::default(),
+                ..create_sut(
+                    TokenAmount::default(),
+                    StakedTokenAmount::default(),
+                    LpTokenAmount::default(),
+                )
+            };
+
+            assert_eq!(
+                sut.tokens_to_staked(TokenAmount(FixedPointDecimal::ONE))
+                    .err(),
+                Some(FixedPointError::DivisionByZero)
+            );
+        }
+    }
+
+    mod fee_accrued_value {
+        use super::*;
+
+        #[test]
+        fn is_zero_right_after_a_deposit() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                .unwrap();
+
+            assert_eq!(sut.fee_accrued_value().unwrap(), TokenAmount::default());
+        }
+
+        #[test]
+        fn equals_the_fee_retained_by_a_swap() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                .unwrap();
+            let input = StakedTokenAmount(FixedPointDecimal::try_from(6).unwrap());
+            let quote = sut.quote_swap_with_fee(&input).unwrap();
+
+            sut.swap(input).unwrap();
+
+            let accrued_value = sut.fee_accrued_value().unwrap();
+            assert!(accrued_value.0 > FixedPointDecimal::default());
+            assert_eq!(accrued_value, quote.fee_amount);
+            assert_eq!(accrued_value.0.raw(), 9_000);
+        }
+
+        #[test]
+        fn undo_restores_the_net_principal() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                .unwrap();
+            sut.swap(StakedTokenAmount(FixedPointDecimal::try_from(6).unwrap()))
+                .unwrap();
+            sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(50).unwrap()))
+                .unwrap();
+            let accrued_value = sut.fee_accrued_value().unwrap();
+
+            sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+                .unwrap();
+            sut.undo().unwrap();
+
+            assert_eq!(sut.fee_accrued_value().unwrap(), accrued_value);
+        }
+
+        #[test]
+        fn partial_removal_takes_its_share_of_fees() {
+            let mut sut = create_sut(
+                TokenAmount::default(),
+                StakedTokenAmount::default(),
+                LpTokenAmount::default(),
+            );
+            sut.add_liquidity(TokenAmount(FixedPointDecimal::try_from(100).unwrap()))
+                .unwrap();
+            sut.swap(StakedTokenAmount(FixedPointDecimal::try_from(6).unwrap()))
+                .unwrap();
+            let (base_tokens, base_staked_tokens) = sut.lp_token_backing().unwrap();
+
+            let (tokens, staked_tokens) = sut
+                .remove_liquidity(LpTokenAmount(FixedPointDecimal::try_from(50).unwrap()))
+                .unwrap();
+
+            // Half of the 0.009 swap fee stays, plus the removal fee kept by the pool
+            let retained_token_fee = ((base_tokens.0 * 50).unwrap() - tokens.0).unwrap();
+            let retained_staked_fee =
+                ((base_staked_tokens.0 * 50).unwrap() - staked_tokens.0).unwrap();
+            let retained_removal_fee = (retained_token_fee
+                + sut
+                    .price
+                    .value_of(&StakedTokenAmount(retained_staked_fee))
+                    .unwrap()
+                    .0)
+                .unwrap();
+            assert_eq!(sut.net_principal.0, 50);
+            assert_eq!(
+                sut.fee_accrued_value().unwrap().0,
+                (FixedPointDecimal::from_raw(4_500) + retained_removal_fee).unwrap()
+            );
         }
     }
 }
@@ -1,12 +1,7 @@
-mod fixed_point_decimal;
-mod liquidity_pool;
-mod tokens;
-mod utils;
-
-use crate::fixed_point_decimal::FixedPointDecimal;
-use crate::liquidity_pool::LiquidityPool;
-use crate::tokens::{LpTokenAmount, StakedTokenAmount, TokenAmount};
-use crate::utils::{Percentage, Price};
+use keep_liquidity::fixed_point_decimal::FixedPointDecimal;
+use keep_liquidity::liquidity_pool::LiquidityPool;
+use keep_liquidity::tokens::{LpTokenAmount, StakedTokenAmount, TokenAmount};
+use keep_liquidity::utils::{Percentage, Price};
 
 fn main() {
     let price = Price(FixedPointDecimal::try_from(1.5).unwrap());
@@ -14,7 +9,8 @@ fn main() {
     let max_fee = Percentage(FixedPointDecimal::try_from(0.09).unwrap());
     let liquidity_target = TokenAmount(FixedPointDecimal::try_from(90.0).unwrap());
 
-    let mut liquidity_pool = LiquidityPool::init(price, liquidity_target, min_fee, max_fee);
+    let mut liquidity_pool =
+        LiquidityPool::init(price, liquidity_target, min_fee, max_fee).unwrap();
     println!("Liquidity pool init done");
     println!("{}", liquidity_pool);
 
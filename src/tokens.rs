@@ -1,8 +1,9 @@
 use core::fmt;
 
-use crate::fixed_point_decimal::FixedPointDecimal;
+use crate::fixed_point_decimal::{FixedPointDecimal, FixedPointError};
+use crate::utils::Percentage;
 
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct TokenAmount(pub FixedPointDecimal);
 
 impl fmt::Display for TokenAmount {
@@ -11,7 +12,19 @@ impl fmt::Display for TokenAmount {
     }
 }
 
-#[derive(Debug, Default, PartialEq, Eq)]
+impl From<FixedPointDecimal> for TokenAmount {
+    fn from(value: FixedPointDecimal) -> Self {
+        TokenAmount(value)
+    }
+}
+
+impl From<TokenAmount> for FixedPointDecimal {
+    fn from(value: TokenAmount) -> Self {
+        value.0
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct StakedTokenAmount(pub FixedPointDecimal);
 
 impl fmt::Display for StakedTokenAmount {
@@ -20,7 +33,19 @@ impl fmt::Display for StakedTokenAmount {
     }
 }
 
-#[derive(Debug, Default, PartialEq, Eq)]
+impl From<FixedPointDecimal> for StakedTokenAmount {
+    fn from(value: FixedPointDecimal) -> Self {
+        StakedTokenAmount(value)
+    }
+}
+
+impl From<StakedTokenAmount> for FixedPointDecimal {
+    fn from(value: StakedTokenAmount) -> Self {
+        value.0
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct LpTokenAmount(pub FixedPointDecimal);
 
 impl fmt::Display for LpTokenAmount {
@@ -28,3 +53,95 @@ impl fmt::Display for LpTokenAmount {
         write!(f, "{}", self.0)
     }
 }
+
+impl LpTokenAmount {
+    pub fn split(
+        self,
+        ratio: Percentage,
+    ) -> Result<(LpTokenAmount, LpTokenAmount), FixedPointError> {
+        let first = ratio.of(self.0)?;
+        let second = (self.0 - first)?;
+        Ok((LpTokenAmount(first), LpTokenAmount(second)))
+    }
+}
+
+impl From<FixedPointDecimal> for LpTokenAmount {
+    fn from(value: FixedPointDecimal) -> Self {
+        LpTokenAmount(value)
+    }
+}
+
+impl From<LpTokenAmount> for FixedPointDecimal {
+    fn from(value: LpTokenAmount) -> Self {
+        value.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_amount_conversions() {
+        let value = FixedPointDecimal::try_from(1.5).unwrap();
+        let token_amount: TokenAmount = value.into();
+        assert_eq!(token_amount, TokenAmount(value));
+        assert_eq!(FixedPointDecimal::from(token_amount), value);
+    }
+
+    #[test]
+    fn test_staked_token_amount_conversions() {
+        let value = FixedPointDecimal::try_from(2.5).unwrap();
+        let staked_token_amount: StakedTokenAmount = value.into();
+        assert_eq!(staked_token_amount, StakedTokenAmount(value));
+        assert_eq!(FixedPointDecimal::from(staked_token_amount), value);
+    }
+
+    #[test]
+    fn test_lp_token_amount_conversions() {
+        let value = FixedPointDecimal::try_from(3.5).unwrap();
+        let lp_token_amount: LpTokenAmount = value.into();
+        assert_eq!(lp_token_amount, LpTokenAmount(value));
+        assert_eq!(FixedPointDecimal::from(lp_token_amount), value);
+    }
+
+    #[test]
+    fn test_lp_token_amount_split_evenly() {
+        let lp_token_amount = LpTokenAmount(FixedPointDecimal::try_from(100).unwrap());
+        let (first, second) = lp_token_amount
+            .split(Percentage(FixedPointDecimal::try_from(0.5).unwrap()))
+            .unwrap();
+
+        assert_eq!(first.0, 50);
+        assert_eq!(second.0, 50);
+    }
+
+    #[test]
+    fn test_lp_token_amount_split_unevenly() {
+        let lp_token_amount = LpTokenAmount(FixedPointDecimal::try_from(100).unwrap());
+        let (first, second) = lp_token_amount
+            .split(Percentage(FixedPointDecimal::try_from(0.3).unwrap()))
+            .unwrap();
+
+        assert_eq!(first.0, 30);
+        assert_eq!(second.0, 70);
+    }
+
+    #[test]
+    fn test_lp_token_amount_split_sums_to_original() {
+        let original = FixedPointDecimal::try_from(10.000001).unwrap();
+        let (first, second) = LpTokenAmount(original)
+            .split(Percentage(FixedPointDecimal::try_from(0.333333).unwrap()))
+            .unwrap();
+
+        assert_eq!((first.0 + second.0).unwrap(), original);
+    }
+
+    #[test]
+    fn test_lp_token_amount_split_rejects_ratio_above_one() {
+        let result = LpTokenAmount(FixedPointDecimal::try_from(100).unwrap())
+            .split(Percentage(FixedPointDecimal::try_from(1.5).unwrap()));
+
+        assert_eq!(result.err().unwrap(), FixedPointError::Underflow);
+    }
+}
@@ -1,7 +1,349 @@
-use crate::fixed_point_decimal::FixedPointDecimal;
+use crate::fixed_point_decimal::{FixedPointDecimal, FixedPointError, FACTOR};
+use crate::tokens::{StakedTokenAmount, TokenAmount};
 
-#[derive(Debug, Default)]
+const RAW_PER_PERMILLE: u64 = FACTOR / 1_000;
+const RAW_PER_BASIS_POINT: u64 = FACTOR / 10_000;
+const RAW_PER_PPM: u64 = FACTOR / 1_000_000;
+
+/// Price of one staked token in base tokens.
+///
+/// Pool math uses the typed methods below, such as `value_of` and `scale_by`,
+/// instead of multiplying the raw fixed-point values directly.
+#[derive(Debug, Default, Clone)]
 pub struct Price(pub FixedPointDecimal);
 
-#[derive(Debug, Default)]
+impl Price {
+    pub fn value_of(
+        &self,
+        staked_token_amount: &StakedTokenAmount,
+    ) -> Result<TokenAmount, FixedPointError> {
+        Ok(TokenAmount((self.0 * staked_token_amount.0)?))
+    }
+
+    pub fn staked_for(
+        &self,
+        token_amount: &TokenAmount,
+    ) -> Result<StakedTokenAmount, FixedPointError> {
+        Ok(StakedTokenAmount((token_amount.0 / self.0)?))
+    }
+
+    pub fn scale_by(&self, percentage: Percentage) -> Result<Price, FixedPointError> {
+        Ok(Price((self.0 * percentage.0)?))
+    }
+}
+
+/// Converts an amount of one token into another through `P`, e.g. a `Price`.
+///
+/// Amounts are carried as `TokenAmount` on both sides so routing code can chain
+/// conversions without caring which pool side it is on.
+pub trait ConvertVia<P> {
+    fn convert(&self, amount: TokenAmount, via: &P) -> Result<TokenAmount, FixedPointError>;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StakedToBase;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BaseToStaked;
+
+impl ConvertVia<Price> for StakedToBase {
+    fn convert(&self, amount: TokenAmount, via: &Price) -> Result<TokenAmount, FixedPointError> {
+        via.value_of(&StakedTokenAmount(amount.0))
+    }
+}
+
+impl ConvertVia<Price> for BaseToStaked {
+    fn convert(&self, amount: TokenAmount, via: &Price) -> Result<TokenAmount, FixedPointError> {
+        Ok(TokenAmount(via.staked_for(&amount)?.0))
+    }
+}
+
+impl From<FixedPointDecimal> for Price {
+    fn from(value: FixedPointDecimal) -> Self {
+        Price(value)
+    }
+}
+
+impl From<Price> for FixedPointDecimal {
+    fn from(value: Price) -> Self {
+        value.0
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct Percentage(pub FixedPointDecimal);
+
+impl From<FixedPointDecimal> for Percentage {
+    fn from(value: FixedPointDecimal) -> Self {
+        Percentage(value)
+    }
+}
+
+impl From<Percentage> for FixedPointDecimal {
+    fn from(value: Percentage) -> Self {
+        value.0
+    }
+}
+
+impl Percentage {
+    pub fn of(&self, amount: FixedPointDecimal) -> Result<FixedPointDecimal, FixedPointError> {
+        self.0 * amount
+    }
+
+    pub fn clamped(&self) -> Percentage {
+        if self.0 > FixedPointDecimal::ONE {
+            Percentage(FixedPointDecimal::ONE)
+        } else {
+            Percentage(self.0)
+        }
+    }
+
+    /// `part / whole`, clamped to `ONE`. Use `from_ratio_unclamped` to keep shares above 100%.
+    pub fn from_ratio(
+        part: TokenAmount,
+        whole: TokenAmount,
+    ) -> Result<Percentage, FixedPointError> {
+        Ok(Percentage::from_ratio_unclamped(part, whole)?.clamped())
+    }
+
+    pub fn from_ratio_unclamped(
+        part: TokenAmount,
+        whole: TokenAmount,
+    ) -> Result<Percentage, FixedPointError> {
+        Ok(Percentage((part.0 / whole.0)?))
+    }
+
+    pub fn from_permille(permille: u64) -> Result<Percentage, FixedPointError> {
+        let raw_value = permille
+            .checked_mul(RAW_PER_PERMILLE)
+            .ok_or(FixedPointError::Overflow)?;
+        Ok(Percentage(FixedPointDecimal::from_raw(raw_value)))
+    }
+
+    pub fn from_basis_points(bps: u64) -> Result<Percentage, FixedPointError> {
+        let raw_value = bps
+            .checked_mul(RAW_PER_BASIS_POINT)
+            .ok_or(FixedPointError::Overflow)?;
+        Ok(Percentage(FixedPointDecimal::from_raw(raw_value)))
+    }
+
+    pub fn to_basis_points(&self) -> Result<u64, FixedPointError> {
+        let raw_value = self.0.raw();
+        let basis_points = raw_value / RAW_PER_BASIS_POINT;
+        if (raw_value % RAW_PER_BASIS_POINT) * 2 >= RAW_PER_BASIS_POINT {
+            basis_points.checked_add(1).ok_or(FixedPointError::Overflow)
+        } else {
+            Ok(basis_points)
+        }
+    }
+
+    pub fn from_ppm(ppm: u64) -> Result<Percentage, FixedPointError> {
+        let raw_value = ppm
+            .checked_mul(RAW_PER_PPM)
+            .ok_or(FixedPointError::Overflow)?;
+        Ok(Percentage(FixedPointDecimal::from_raw(raw_value)))
+    }
+
+    pub fn to_ppm(&self) -> Result<u64, FixedPointError> {
+        Ok(self.0.raw() / RAW_PER_PPM)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_conversions() {
+        let value = FixedPointDecimal::try_from(1.5).unwrap();
+        let price: Price = value.into();
+        assert_eq!(price.0, value);
+        assert_eq!(FixedPointDecimal::from(price), value);
+    }
+
+    #[test]
+    fn test_percentage_conversions() {
+        let value = FixedPointDecimal::try_from(0.09).unwrap();
+        let percentage: Percentage = value.into();
+        assert_eq!(percentage.0, value);
+        assert_eq!(FixedPointDecimal::from(percentage), value);
+    }
+
+    #[test]
+    fn test_wrappers_convert_into_fixed_point_generically() {
+        use crate::tokens::LpTokenAmount;
+
+        fn raw_of(value: impl Into<FixedPointDecimal>) -> u64 {
+            value.into().raw()
+        }
+
+        let value = FixedPointDecimal::try_from(1.5).unwrap();
+        assert_eq!(raw_of(Price(value)), 1_500_000);
+        assert_eq!(raw_of(Percentage(value)), 1_500_000);
+        assert_eq!(raw_of(TokenAmount(value)), 1_500_000);
+        assert_eq!(raw_of(StakedTokenAmount(value)), 1_500_000);
+        assert_eq!(raw_of(LpTokenAmount(value)), 1_500_000);
+    }
+
+    #[test]
+    fn test_price_value_of_and_staked_for() {
+        let price = Price(FixedPointDecimal::try_from(1.5).unwrap());
+        let token_amount = price
+            .value_of(&StakedTokenAmount(FixedPointDecimal::try_from(10).unwrap()))
+            .unwrap();
+        assert_eq!(token_amount.0, 15);
+
+        let staked_token_amount = price.staked_for(&token_amount).unwrap();
+        assert_eq!(staked_token_amount.0, 10);
+    }
+
+    #[test]
+    fn test_price_scale_by() {
+        let price = Price(FixedPointDecimal::try_from(1.5).unwrap());
+        let scaled = price
+            .scale_by(Percentage(FixedPointDecimal::try_from(0.5).unwrap()))
+            .unwrap();
+        assert_eq!(scaled.0, FixedPointDecimal::try_from(0.75).unwrap());
+    }
+
+    #[test]
+    fn test_percentage_of() {
+        let fee = Percentage(FixedPointDecimal::try_from(0.001).unwrap());
+        let fee_value = fee.of(FixedPointDecimal::try_from(15).unwrap()).unwrap();
+        assert_eq!(fee_value, FixedPointDecimal::try_from(0.015).unwrap());
+    }
+
+    #[test]
+    fn test_percentage_clamped() {
+        let fee = Percentage(FixedPointDecimal::try_from(1.5).unwrap());
+        assert_eq!(fee.clamped().0, 1);
+
+        let fee = Percentage(FixedPointDecimal::try_from(0.09).unwrap());
+        assert_eq!(fee.clamped().0, FixedPointDecimal::try_from(0.09).unwrap());
+    }
+
+    #[test]
+    fn test_percentage_from_ratio() {
+        let part = TokenAmount(FixedPointDecimal::try_from(45u64).unwrap());
+        let whole = TokenAmount(FixedPointDecimal::try_from(180u64).unwrap());
+
+        let share = Percentage::from_ratio(part, whole).unwrap();
+        assert_eq!(share.0, FixedPointDecimal::try_from(0.25).unwrap());
+    }
+
+    #[test]
+    fn test_percentage_from_ratio_clamps_above_one() {
+        let part = TokenAmount(FixedPointDecimal::try_from(270u64).unwrap());
+        let whole = TokenAmount(FixedPointDecimal::try_from(180u64).unwrap());
+
+        let share = Percentage::from_ratio(part.clone(), whole.clone()).unwrap();
+        assert_eq!(share.0, FixedPointDecimal::ONE);
+
+        let share = Percentage::from_ratio_unclamped(part, whole).unwrap();
+        assert_eq!(share.0, FixedPointDecimal::try_from(1.5).unwrap());
+    }
+
+    #[test]
+    fn test_percentage_from_ratio_zero_whole() {
+        let result =
+            Percentage::from_ratio(TokenAmount(FixedPointDecimal::ONE), TokenAmount::default());
+        assert_eq!(result.err().unwrap(), FixedPointError::DivisionByZero);
+    }
+
+    #[test]
+    fn test_percentage_from_permille() {
+        let fee = Percentage::from_permille(90).unwrap();
+        assert_eq!(fee.0, FixedPointDecimal::try_from(0.09).unwrap());
+
+        let fee = Percentage::from_permille(1).unwrap();
+        assert_eq!(fee.0, FixedPointDecimal::try_from(0.001).unwrap());
+
+        let result = Percentage::from_permille(u64::MAX);
+        assert_eq!(result.err().unwrap(), FixedPointError::Overflow);
+    }
+
+    #[test]
+    fn test_percentage_from_basis_points() {
+        let fee = Percentage::from_basis_points(900).unwrap();
+        assert_eq!(fee.0, FixedPointDecimal::try_from(0.09).unwrap());
+
+        let fee = Percentage::from_basis_points(10).unwrap();
+        assert_eq!(fee.0, FixedPointDecimal::try_from(0.001).unwrap());
+    }
+
+    #[test]
+    fn test_percentage_from_basis_points_overflow() {
+        let result = Percentage::from_basis_points(u64::MAX);
+        assert_eq!(result.err().unwrap(), FixedPointError::Overflow);
+    }
+
+    #[test]
+    fn test_percentage_basis_points_round_trip() {
+        for bps in [0, 1, 10, 900, 10_000] {
+            let fee = Percentage::from_basis_points(bps).unwrap();
+            assert_eq!(fee.to_basis_points().unwrap(), bps);
+        }
+    }
+
+    #[test]
+    fn test_percentage_to_basis_points_rounds_to_nearest() {
+        let fee = Percentage(FixedPointDecimal::try_from(0.00125).unwrap());
+        assert_eq!(fee.to_basis_points().unwrap(), 13);
+
+        let fee = Percentage(FixedPointDecimal::try_from(0.00124).unwrap());
+        assert_eq!(fee.to_basis_points().unwrap(), 12);
+    }
+
+    #[test]
+    fn test_percentage_from_ppm() {
+        let fee = Percentage::from_ppm(90_000).unwrap();
+        assert_eq!(fee.0, FixedPointDecimal::try_from(0.09).unwrap());
+
+        let fee = Percentage::from_ppm(1_000).unwrap();
+        assert_eq!(fee.0, FixedPointDecimal::try_from(0.001).unwrap());
+    }
+
+    #[test]
+    fn test_percentage_ppm_round_trip() {
+        for ppm in [0, 1, 999, 1_000, 90_000, 1_000_000] {
+            let fee = Percentage::from_ppm(ppm).unwrap();
+            assert_eq!(fee.to_ppm().unwrap(), ppm);
+        }
+    }
+
+    fn convert_through<C: ConvertVia<Price>>(
+        conversion: C,
+        amount: TokenAmount,
+        prices: &[Price],
+    ) -> Result<TokenAmount, FixedPointError> {
+        prices
+            .iter()
+            .try_fold(amount, |amount, price| conversion.convert(amount, price))
+    }
+
+    #[test]
+    fn test_convert_via_price() {
+        let price = Price(FixedPointDecimal::try_from(1.5).unwrap());
+        let amount = TokenAmount(FixedPointDecimal::try_from(10u64).unwrap());
+
+        let base = StakedToBase.convert(amount.clone(), &price).unwrap();
+        assert_eq!(base.0, FixedPointDecimal::try_from(15u64).unwrap());
+
+        let staked = BaseToStaked.convert(base, &price).unwrap();
+        assert_eq!(staked, amount);
+    }
+
+    #[test]
+    fn test_convert_via_is_generic_over_direction() {
+        let prices = [
+            Price(FixedPointDecimal::try_from(2u64).unwrap()),
+            Price(FixedPointDecimal::try_from(1.5).unwrap()),
+        ];
+        let amount = TokenAmount(FixedPointDecimal::try_from(10u64).unwrap());
+
+        let base = convert_through(StakedToBase, amount.clone(), &prices).unwrap();
+        assert_eq!(base.0, FixedPointDecimal::try_from(30u64).unwrap());
+        let staked = convert_through(BaseToStaked, base, &prices).unwrap();
+        assert_eq!(staked, amount);
+    }
+}